@@ -0,0 +1,287 @@
+use std::{fmt, marker::PhantomData};
+
+use nalgebra::{Point3, Vector3};
+use serde::{
+    de::{self, IntoDeserializer, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+/// A value that can follow a [`Curve`].
+pub trait Animatable: Copy {
+    /// Returns `self` moved by `t` times the difference from `a` to `b`.
+    fn add_scaled(self, a: Self, b: Self, t: f64) -> Self;
+}
+
+impl Animatable for f64 {
+    fn add_scaled(self, a: Self, b: Self, t: f64) -> Self {
+        self + t * (b - a)
+    }
+}
+
+impl Animatable for Vector3<f64> {
+    fn add_scaled(self, a: Self, b: Self, t: f64) -> Self {
+        self + t * (b - a)
+    }
+}
+
+impl Animatable for Point3<f64> {
+    fn add_scaled(self, a: Self, b: Self, t: f64) -> Self {
+        self + t * (b - a)
+    }
+}
+
+/// How a [`Curve`] gets from one key to the next.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    /// In a straight line at a constant speed
+    #[default]
+    Linear,
+    /// Along a Catmull-Rom spline, which passes through every key without sudden changes of
+    /// direction
+    CatmullRom,
+}
+
+/// The value a [`Curve`] passes through at a time.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Key<T> {
+    pub time: f64,
+    pub value: T,
+}
+
+/// A value that changes over time, passing through each of its keys. Before the first key and
+/// after the last it holds still. Times are counted in frames, so a still image sees the curve
+/// between times 0 and 1.
+///
+/// In a scene file a curve is either a plain value, which never changes, or a table such as
+/// `{ interpolation = "catmull_rom", keys = [{ time = 0, value = 1 }, { time = 24, value = 2 }] }`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Curve<T> {
+    interpolation: Interpolation,
+    /// The keys in time order, of which there is at least one.
+    keys: Vec<Key<T>>,
+}
+
+impl<T: Animatable> Curve<T> {
+    /// Creates a curve that always has the same value.
+    pub fn constant(value: T) -> Curve<T> {
+        Curve {
+            interpolation: Interpolation::Linear,
+            keys: vec![Key { time: 0., value }],
+        }
+    }
+
+    /// Creates a curve through `keys`, which can be in any order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no keys.
+    pub fn new(interpolation: Interpolation, mut keys: Vec<Key<T>>) -> Curve<T> {
+        assert!(!keys.is_empty(), "a curve needs at least one key");
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Curve {
+            interpolation,
+            keys,
+        }
+    }
+
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    /// Returns the keys in time order.
+    pub fn keys(&self) -> &[Key<T>] {
+        &self.keys
+    }
+
+    /// Returns whether the curve has the same value at all times.
+    pub fn is_constant(&self) -> bool {
+        self.keys.len() == 1
+    }
+
+    /// Returns whether the curve holds the same value from `start` to `end`, which it does when
+    /// that time is all before the first key or all after the last.
+    pub fn is_still(&self, start: f64, end: f64) -> bool {
+        let first = self.keys[0].time;
+        let last = self.keys[self.keys.len() - 1].time;
+        self.is_constant() || start == end || end <= first || start >= last
+    }
+
+    /// Returns the value at `time`.
+    pub fn at(&self, time: f64) -> T {
+        let keys = &self.keys;
+        let after = keys.partition_point(|k| k.time <= time);
+        if after == 0 {
+            return keys[0].value;
+        }
+        if after == keys.len() {
+            return keys[after - 1].value;
+        }
+
+        let i = after - 1;
+        let t = (time - keys[i].time) / (keys[i + 1].time - keys[i].time);
+        let [b0, b1, b2, b3] = match self.interpolation {
+            Interpolation::Linear => return lerp(keys[i].value, keys[i + 1].value, t),
+            Interpolation::CatmullRom => self.bezier(i),
+        };
+
+        // De Casteljau's algorithm
+        let c0 = lerp(b0, b1, t);
+        let c1 = lerp(b1, b2, t);
+        let c2 = lerp(b2, b3, t);
+        lerp(lerp(c0, c1, t), lerp(c1, c2, t), t)
+    }
+
+    /// Returns points whose convex hull holds every value the curve takes, which is what
+    /// bounding boxes of moving objects are built from.
+    pub fn hull(&self) -> Vec<T> {
+        match self.interpolation {
+            Interpolation::Linear => self.keys.iter().map(|k| k.value).collect(),
+            Interpolation::CatmullRom => {
+                let mut points = vec![self.keys[0].value];
+                for i in 0..self.keys.len() - 1 {
+                    points.extend_from_slice(&self.bezier(i)[1..]);
+                }
+                points
+            }
+        }
+    }
+
+    /// Returns the control points of the cubic Bezier curve the Catmull-Rom spline follows
+    /// from key `i` to key `i + 1`.
+    fn bezier(&self, i: usize) -> [T; 4] {
+        let gap = self.keys[i + 1].time - self.keys[i].time;
+        [
+            self.keys[i].value,
+            self.along_tangent(i, gap / 3.),
+            self.along_tangent(i + 1, -gap / 3.),
+            self.keys[i + 1].value,
+        ]
+    }
+
+    /// Returns the value at key `i` moved along the spline's tangent there for `time`. The
+    /// tangent is the change between the keys either side over the time between them, so the
+    /// speed carries on smoothly through keys that aren't evenly spaced. The keys at the ends are
+    /// repeated to give the spline somewhere to start and finish, as far away as the key inside.
+    fn along_tangent(&self, i: usize, time: f64) -> T {
+        let keys = &self.keys;
+        let before = &keys[i.saturating_sub(1)];
+        let after = &keys[(i + 1).min(keys.len() - 1)];
+        let mut span = after.time - before.time;
+        if i == 0 || i == keys.len() - 1 {
+            span *= 2.;
+        }
+        if span == 0. {
+            return keys[i].value;
+        }
+        let value = keys[i].value;
+        value.add_scaled(before.value, after.value, time / span)
+    }
+}
+
+fn lerp<T: Animatable>(a: T, b: T, t: f64) -> T {
+    a.add_scaled(a, b, t)
+}
+
+/// The table form of a [`Curve`] in a scene file.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyedCurve<T> {
+    #[serde(default)]
+    interpolation: Interpolation,
+    keys: Vec<Key<T>>,
+}
+
+impl<T: Animatable + Serialize> Serialize for Curve<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.is_constant() {
+            return self.keys[0].value.serialize(serializer);
+        }
+        KeyedCurve {
+            interpolation: self.interpolation,
+            keys: self.keys.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T: Animatable + Deserialize<'de>> Deserialize<'de> for Curve<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CurveVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Animatable + Deserialize<'de>> Visitor<'de> for CurveVisitor<T> {
+            type Value = Curve<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a value or a table of keys")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                Ok(Curve::constant(T::deserialize(v.into_deserializer())?))
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                Ok(Curve::constant(T::deserialize(v.into_deserializer())?))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                let value = T::deserialize(de::value::SeqAccessDeserializer::new(seq))?;
+                Ok(Curve::constant(value))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let keyed = KeyedCurve::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                if keyed.keys.is_empty() {
+                    return Err(de::Error::custom("a curve needs at least one key"));
+                }
+                Ok(Curve::new(keyed.interpolation, keyed.keys))
+            }
+        }
+
+        deserializer.deserialize_any(CurveVisitor(PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(time: f64, value: f64) -> Key<f64> {
+        Key { time, value }
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_keys_without_jumps_in_speed() {
+        let keys = vec![key(0., 0.), key(1., 1.), key(10., 2.), key(12., 0.)];
+        let curve = Curve::new(Interpolation::CatmullRom, keys.clone());
+        for key in &keys {
+            assert!((curve.at(key.time) - key.value).abs() < 1e-12);
+        }
+
+        // The speed just before each key inside matches the speed just after it, even though
+        // the keys are far from evenly spaced
+        let h = 1e-6;
+        for key in &keys[1..keys.len() - 1] {
+            let before = (curve.at(key.time) - curve.at(key.time - h)) / h;
+            let after = (curve.at(key.time + h) - curve.at(key.time)) / h;
+            assert!((before - after).abs() < 1e-4, "{before} then {after}");
+        }
+    }
+
+    #[test]
+    fn catmull_rom_keeps_rising_between_rising_keys() {
+        // Short steps either side of a long one would overshoot and turn back if the tangents
+        // ignored the spacing
+        let curve = Curve::new(
+            Interpolation::CatmullRom,
+            vec![key(0., 0.), key(1., 0.1), key(10., 1.), key(11., 2.)],
+        );
+        let mut last = 0.;
+        for step in 0..=11000 {
+            let value = curve.at(step as f64 / 1000.);
+            assert!(value >= last - 1e-12 && value <= 2.);
+            last = value;
+        }
+    }
+}
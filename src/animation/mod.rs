@@ -0,0 +1,3 @@
+mod curve;
+
+pub use curve::{Animatable, Curve, Interpolation, Key};
@@ -0,0 +1,382 @@
+use std::path::{Path, PathBuf};
+
+use clap::{builder::PossibleValuesParser, Parser, ValueEnum};
+use image::ImageFormat;
+
+use crate::{
+    core::{check_positive, CameraBuilder, Projection, ShutterCurve},
+    film::{Aov, Filter, FrameBuffer, ToneMap},
+    sampling::SamplerKind,
+    scenes::SCENES,
+};
+
+#[derive(Parser)]
+#[command(about = "A path tracer based on the Ray Tracing in One Weekend series")]
+pub struct Args {
+    /// Built-in scene to render
+    #[arg(short, long, default_value = "cornel_box", value_parser = scene_names())]
+    pub scene: String,
+
+    /// Scene file to render instead of a built-in scene
+    #[arg(short, long, conflicts_with = "scene")]
+    pub file: Option<PathBuf>,
+
+    /// Whether to show the render in a window or write it straight to a file
+    #[arg(short, long, value_enum, default_value_t = Mode::Gui)]
+    pub mode: Mode,
+
+    /// In GUI mode, render the whole image one sample per pixel at a time so it can be watched
+    /// sharpening, rather than finishing each pixel in turn
+    #[arg(short, long)]
+    pub progressive: bool,
+
+    /// Image to write in headless mode, the format is chosen from the extension. `.exr`, `.hdr`
+    /// and `.pfm` keep the unclamped linear radiance
+    #[arg(short, long, default_value = "output.png", value_parser = parse_output)]
+    pub output: PathBuf,
+
+    /// Write the scene, including any camera changes, to this scene file instead of rendering
+    #[arg(short, long)]
+    pub export: Option<PathBuf>,
+
+    /// Width of the image in pixels
+    #[arg(short = 'w', long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub image_width: Option<u32>,
+
+    /// Number of samples taken for each pixel, or the most taken with adaptive sampling
+    #[arg(short = 'n', long)]
+    pub samples_per_pixel: Option<u32>,
+
+    /// Stop sampling a pixel once its noise falls below this, zero turns adaptive sampling off
+    #[arg(long)]
+    pub noise_threshold: Option<f64>,
+
+    /// Samples every pixel gets before adaptive sampling may stop early
+    #[arg(long)]
+    pub min_samples_per_pixel: Option<u32>,
+
+    /// In headless mode, also write an image showing how many samples each pixel took
+    #[arg(long, value_parser = parse_heatmap)]
+    pub heatmap: Option<PathBuf>,
+
+    /// In headless mode, also write these AOVs, each to a float image named after the output
+    /// with the AOV's name added, such as `output.normal.exr`
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub aovs: Vec<Aov>,
+
+    /// Write the AOVs as layers of the output image rather than as separate images, which needs
+    /// the output to be an `.exr`
+    #[arg(long, requires = "aovs")]
+    pub aov_layers: bool,
+
+    /// In headless mode, save the progress of the render to this file every so often, so that
+    /// it can be carried on with `--resume` if it is stopped
+    #[arg(long)]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Seconds between saving the progress to `--checkpoint`
+    #[arg(long, requires = "checkpoint", value_parser = parse_positive, default_value_t = 60.)]
+    pub checkpoint_interval: f64,
+
+    /// Carry on the render saved in `--checkpoint`, which may be taken to more samples per pixel
+    /// than it was started with. The scene and the rest of the camera settings must not change
+    #[arg(long, requires = "checkpoint")]
+    pub resume: bool,
+
+    /// Address the coordinator listens on and workers connect to
+    #[arg(long, default_value = "127.0.0.1:7878")]
+    pub address: String,
+
+    /// Width and height in pixels of the tiles the coordinator hands out
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u32).range(1..))]
+    pub tile_size: u32,
+
+    /// Seconds the coordinator waits to hear from a worker before giving its tile to another.
+    /// Workers check in every second while rendering
+    #[arg(long, default_value_t = 30., value_parser = parse_positive)]
+    pub worker_timeout: f64,
+
+    /// Remove noise from the image with a filter guided by the albedo, normals and depth. In
+    /// GUI mode this sets whether the preview starts out denoised
+    #[arg(long)]
+    pub denoise: bool,
+
+    /// Seed for the random numbers used to build and render the scene. The same seed always
+    /// gives the same image
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// How the random decisions for each pixel's samples are spread
+    #[arg(long, value_enum)]
+    pub sampler: Option<SamplerKind>,
+
+    /// Filter used to reconstruct each pixel from its samples
+    #[arg(long, value_enum)]
+    pub filter: Option<Filter>,
+
+    /// Radius of the pixel filter in pixels, by default the usual radius for the filter
+    #[arg(long, value_parser = parse_positive)]
+    pub filter_radius: Option<f64>,
+
+    /// Curve used to bring the radiance into the range a display can show
+    #[arg(long, value_enum)]
+    pub tone_map: Option<ToneMap>,
+
+    /// Exposure adjustment in stops, each of which doubles the brightness
+    #[arg(long, allow_negative_numbers = true)]
+    pub exposure: Option<f64>,
+
+    /// Luminance shown as white by the extended Reinhard tone map
+    #[arg(long, value_parser = parse_positive)]
+    pub white_point: Option<f64>,
+
+    /// In headless mode, print how far the render is from this converged `.exr`, `.hdr` or
+    /// `.pfm` image of the same scene, as a root mean square error
+    #[arg(long)]
+    pub reference: Option<PathBuf>,
+
+    /// Maximum number of bounces for each ray
+    #[arg(short = 'd', long)]
+    pub max_depth: Option<u32>,
+
+    /// Bounces every ray gets before it may be ended at random once it carries little light
+    #[arg(long)]
+    pub russian_roulette_depth: Option<u32>,
+
+    /// How points on the image map to directions in the scene
+    #[arg(long, value_enum)]
+    pub projection: Option<Projection>,
+
+    /// Vertical field of view in degrees
+    #[arg(long, value_parser = parse_positive)]
+    pub vfov: Option<f64>,
+
+    /// The time the shutter opens, which moving objects and camera keyframes are timed against
+    #[arg(long, allow_negative_numbers = true)]
+    pub shutter_open: Option<f64>,
+
+    /// The time the shutter closes
+    #[arg(long, allow_negative_numbers = true)]
+    pub shutter_close: Option<f64>,
+
+    /// How far open the shutter is over the time it is open
+    #[arg(long, value_enum)]
+    pub shutter_curve: Option<ShutterCurve>,
+
+    /// Equirectangular `.exr`, `.hdr` or `.pfm` image to light the scene with in place of its
+    /// background
+    #[arg(long)]
+    pub environment: Option<PathBuf>,
+
+    /// Rotation of the environment map about the vertical axis in degrees
+    #[arg(
+        long,
+        requires = "environment",
+        allow_negative_numbers = true,
+        default_value_t = 0.
+    )]
+    pub environment_rotation: f64,
+
+    /// Scale applied to the radiance of `--environment` or `--sky`
+    #[arg(long, default_value_t = 1.)]
+    pub environment_intensity: f64,
+
+    /// Light the scene with a clear daylight sky and sun in place of its background
+    #[arg(long, conflicts_with = "environment")]
+    pub sky: bool,
+
+    /// Height of the sun above the horizon in degrees
+    #[arg(
+        long,
+        requires = "sky",
+        allow_negative_numbers = true,
+        default_value_t = 45.
+    )]
+    pub sun_elevation: f64,
+
+    /// Direction of the sun around the horizon in degrees, turning from -z towards +x
+    #[arg(
+        long,
+        requires = "sky",
+        allow_negative_numbers = true,
+        default_value_t = 0.
+    )]
+    pub sun_azimuth: f64,
+
+    /// Haziness of the sky, from 2 for very clear air to 10 for a hazy day
+    #[arg(long, requires = "sky", value_parser = parse_turbidity, default_value_t = 3.)]
+    pub turbidity: f64,
+
+    /// Frame of the scene's animation to render. Times are counted in frames, so the shutter
+    /// times are relative to the start of this frame
+    #[arg(long)]
+    pub frame: Option<u32>,
+
+    /// In headless mode, render this many frames of the animation starting from `--frame`.
+    /// Each is written to the output path with its frame number in place of a run of `#`, or
+    /// added to the end of the file name if there is none
+    #[arg(long, conflicts_with = "reference", value_parser = clap::value_parser!(u32).range(1..))]
+    pub frames: Option<u32>,
+
+    /// Width over height of the image, either as a number or a ratio such as `16:9`
+    #[arg(short, long, value_parser = parse_aspect_ratio)]
+    pub aspect_ratio: Option<f64>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Mode {
+    /// Show the image in a window as it renders
+    Gui,
+    /// Render without a display and save the image to the output path
+    Headless,
+    /// Split the image into tiles for workers to render, and save it to the output path once
+    /// they are all done
+    Coordinator,
+    /// Render tiles for the coordinator at `--address`, which must be rendering the same scene
+    Worker,
+}
+
+impl Args {
+    /// Applies the camera settings given on the command line on top of a scene's own.
+    pub fn camera(&self, mut builder: CameraBuilder) -> CameraBuilder {
+        if let Some(image_width) = self.image_width {
+            builder = builder.image_width(image_width);
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            builder = builder.samples_per_pixel(samples_per_pixel);
+        }
+        if let Some(noise_threshold) = self.noise_threshold {
+            builder = builder.noise_threshold(noise_threshold);
+        }
+        if let Some(min_samples_per_pixel) = self.min_samples_per_pixel {
+            builder = builder.min_samples_per_pixel(min_samples_per_pixel);
+        }
+        if let Some(seed) = self.seed {
+            builder = builder.seed(seed);
+        }
+        if let Some(sampler) = self.sampler {
+            builder = builder.sampler(sampler);
+        }
+        if let Some(filter) = self.filter {
+            builder = builder.filter(filter);
+        }
+        if let Some(filter_radius) = self.filter_radius {
+            builder = builder.filter_radius(filter_radius);
+        }
+        if let Some(tone_map) = self.tone_map {
+            builder = builder.tone_map(tone_map);
+        }
+        if let Some(exposure) = self.exposure {
+            builder = builder.exposure(exposure);
+        }
+        if let Some(white_point) = self.white_point {
+            builder = builder.white_point(white_point);
+        }
+        if let Some(max_depth) = self.max_depth {
+            builder = builder.max_depth(max_depth);
+        }
+        if let Some(russian_roulette_depth) = self.russian_roulette_depth {
+            builder = builder.russian_roulette_depth(russian_roulette_depth);
+        }
+        if let Some(projection) = self.projection {
+            builder = builder.projection(projection);
+        }
+        if let Some(vfov) = self.vfov {
+            builder = builder.vfov(vfov);
+        }
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            builder = builder.aspect_ratio(aspect_ratio);
+        }
+        if let Some(shutter_open) = self.shutter_open {
+            builder = builder.shutter_open(shutter_open);
+        }
+        if let Some(shutter_close) = self.shutter_close {
+            builder = builder.shutter_close(shutter_close);
+        }
+        if let Some(shutter_curve) = self.shutter_curve {
+            builder = builder.shutter_curve(shutter_curve);
+        }
+        if let Some(frame) = self.frame {
+            builder = builder.frame(frame);
+        }
+        builder
+    }
+}
+
+/// Returns the path frame number `frame` of an animation is written to. The first run of `#`
+/// in the file name is replaced by the frame number padded to its length, otherwise the number
+/// is added to the end of the name padded to four digits.
+pub fn frame_path(path: &Path, frame: u32) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let name = match name.find('#') {
+        Some(start) => {
+            let len = name[start..]
+                .find(|c| c != '#')
+                .unwrap_or(name.len() - start);
+            let number = format!("{frame:0len$}");
+            format!("{}{number}{}", &name[..start], &name[start + len..])
+        }
+        None => {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            match path.extension() {
+                Some(extension) => format!("{stem}_{frame:04}.{}", extension.to_string_lossy()),
+                None => format!("{stem}_{frame:04}"),
+            }
+        }
+    };
+    path.with_file_name(name)
+}
+
+/// Returns the path `aov` is written to when the image is written to `path`, which has the name
+/// of the AOV added before the extension. AOVs of 8-bit images are written as `.exr`.
+pub fn aov_path(path: &Path, aov: Aov) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = match path.extension() {
+        Some(extension) if FrameBuffer::is_float_format(path) => extension.to_string_lossy(),
+        _ => "exr".into(),
+    };
+    path.with_file_name(format!("{stem}.{}.{extension}", aov.name()))
+}
+
+fn scene_names() -> PossibleValuesParser {
+    PossibleValuesParser::new(SCENES.iter().map(|(name, _)| *name))
+}
+
+fn parse_output(s: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(s);
+    if !FrameBuffer::is_float_format(&path) {
+        ImageFormat::from_path(&path).map_err(|e| e.to_string())?;
+    }
+    Ok(path)
+}
+
+fn parse_heatmap(s: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(s);
+    ImageFormat::from_path(&path).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+fn parse_positive(s: &str) -> Result<f64, String> {
+    let value = s.trim().parse::<f64>();
+    check_positive(value.map_err(|_| format!("expected a positive number, got `{s}`"))?)
+}
+
+fn parse_turbidity(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(value) if (2. ..=10.).contains(&value) => Ok(value),
+        _ => Err(format!("expected a number between 2 and 10, got `{s}`")),
+    }
+}
+
+fn parse_aspect_ratio(s: &str) -> Result<f64, String> {
+    let ratio = match s.split_once([':', '/']) {
+        Some((w, h)) => {
+            let w: f64 = w.trim().parse().map_err(|_| format!("invalid width `{w}`"))?;
+            let h: f64 = h.trim().parse().map_err(|_| format!("invalid height `{h}`"))?;
+            w / h
+        }
+        None => s.trim().parse().map_err(|_| format!("invalid aspect ratio `{s}`"))?,
+    };
+
+    check_positive(ratio).map_err(|_| format!("aspect ratio must be positive, got `{s}`"))
+}
@@ -1,63 +1,130 @@
-use image::{ImageBuffer, Rgb};
-use indicatif::ProgressBar;
+use image::{ImageBuffer, ImageResult, Rgb, RgbImage};
+use indicatif::{ProgressBar, ProgressStyle};
 use nalgebra::{vector, Point3, Vector3};
-use rand::random;
 use rayon::prelude::*;
-use std::sync::mpsc::{self, Receiver};
-use std::sync::Arc;
-use std::thread;
-
-use super::{camera_builder::CameraBuilder, Hittable, Ray};
-use crate::utility::{random::rng_in_unit_disk, Interval};
+use std::f64::consts::PI;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::{
+    camera_builder::CameraBuilder, CameraKeyframe, Counter, Hittable, MaterialIds, Projection, Ray,
+    RenderCounters, RenderJob,
+};
+use crate::{
+    animation::{Animatable, Curve, Interpolation, Key},
+    film::{AovBuffer, AovSample, AovStats, Checkpoint, FilterSampler, FrameBuffer, PixelStats},
+    lights::{Environment, LightList},
+    sampling::{self, square_to_disk},
+    utility::{linear_to_srgb, random, Interval},
+};
 
 pub struct PixelData {
     pub index: u32,
     pub colour: Rgb<u8>,
+    /// The unclamped linear radiance that `colour` was made from.
+    pub radiance: Vector3<f64>,
+    /// The pass this pixel was rendered in, counting from 1. Only progressive renders have more
+    /// than one pass.
+    pub pass: u32,
+    /// What the pixel first sees, averaged over the same samples as `radiance`.
+    pub aovs: AovSample,
 }
 
 pub struct Camera {
-    aspect_ratio: f64,
-    image_width: u32,
-    samples_per_pixel: u32,
-    max_depth: u32,
-    vfov: f64,
-    lookat: Point3<f64>,
-    lookfrom: Point3<f64>,
-    vup: Vector3<f64>,
-    defocus_angle: f64,
-    focus_dist: f64,
-    background: Vector3<f64>,
+    settings: CameraBuilder,
     image_height: u32,
+    /// The view used for every ray unless `path` is set. It is from `lookfrom`, or from the
+    /// keyframes if the camera holds still while the shutter is open.
+    view: View,
+    /// The curves the camera follows, if it has keyframes and moves while the shutter is open.
+    path: Option<CameraPath>,
+    filter_sampler: FilterSampler,
+}
+
+/// The keyframes of a camera as a curve for each setting they hold.
+struct CameraPath {
+    lookfrom: Curve<Point3<f64>>,
+    lookat: Curve<Point3<f64>>,
+    vfov: Curve<f64>,
+}
+
+impl CameraPath {
+    fn new(interpolation: Interpolation, keyframes: &[CameraKeyframe]) -> Option<CameraPath> {
+        if keyframes.is_empty() {
+            return None;
+        }
+        Some(CameraPath {
+            lookfrom: keyframe_curve(interpolation, keyframes, |k| k.lookfrom),
+            lookat: keyframe_curve(interpolation, keyframes, |k| k.lookat),
+            vfov: keyframe_curve(interpolation, keyframes, |k| k.vfov),
+        })
+    }
+
+    /// Returns whether the camera holds still from `start` to `end`.
+    fn is_still(&self, start: f64, end: f64) -> bool {
+        self.lookfrom.is_still(start, end)
+            && self.lookat.is_still(start, end)
+            && self.vfov.is_still(start, end)
+    }
+
+    /// Returns the view at `time`.
+    fn view(&self, settings: &CameraBuilder, image_height: u32, time: f64) -> View {
+        let (lookfrom, lookat) = (self.lookfrom.at(time), self.lookat.at(time));
+        View::new(settings, image_height, lookfrom, lookat, self.vfov.at(time))
+    }
+}
+
+fn keyframe_curve<T: Animatable>(
+    interpolation: Interpolation,
+    keyframes: &[CameraKeyframe],
+    value: impl Fn(&CameraKeyframe) -> T,
+) -> Curve<T> {
+    let keys = keyframes
+        .iter()
+        .map(|k| Key {
+            time: k.time,
+            value: value(k),
+        })
+        .collect();
+    Curve::new(interpolation, keys)
+}
+
+/// Where the camera is and which way it faces at one moment.
+struct View {
     center: Point3<f64>,
-    pixel00_loc: Point3<f64>,
-    delta_u: Vector3<f64>,
-    delta_v: Vector3<f64>,
+    /// The distance from `lookfrom` to `lookat`.
+    look_dist: f64,
+    vfov: f64,
+    viewport_upper_left: Point3<f64>,
     u: Vector3<f64>,
     v: Vector3<f64>,
     w: Vector3<f64>,
+    delta_u: Vector3<f64>,
+    delta_v: Vector3<f64>,
     defocus_disk_u: Vector3<f64>,
     defocus_disk_v: Vector3<f64>,
+    /// Whether there is any defocus blur, so the lens needs sampling.
+    defocus: bool,
 }
 
-impl Camera {
-    pub fn builder() -> CameraBuilder {
-        CameraBuilder::default()
-    }
-
-    pub fn new(
-        aspect_ratio: f64,
-        image_width: u32,
-        samples_per_pixel: u32,
-        max_depth: u32,
-        vfov: f64,
-        lookat: Point3<f64>,
+impl View {
+    fn new(
+        settings: &CameraBuilder,
+        image_height: u32,
         lookfrom: Point3<f64>,
-        vup: Vector3<f64>,
-        defocus_angle: f64,
-        focus_dist: f64,
-        background: Vector3<f64>,
-    ) -> Camera {
-        let image_height = (image_width as f64 / aspect_ratio) as u32;
+        lookat: Point3<f64>,
+        vfov: f64,
+    ) -> View {
+        let CameraBuilder {
+            image_width,
+            vup,
+            defocus_angle,
+            focus_dist,
+            ..
+        } = *settings;
 
         let center = lookfrom;
 
@@ -81,165 +148,736 @@ impl Camera {
 
         // Location of upper left
         let viewport_upper_left = center - (focus_dist * w) - (viewport_u / 2.) - (viewport_v / 2.);
-        let pixel00_loc = viewport_upper_left + 0.5 * (delta_u + delta_v);
 
         let defocus_radius = focus_dist * (defocus_angle / 2.).to_radians().tan();
         let defocus_disk_u = u * defocus_radius;
         let defocus_disk_v = v * defocus_radius;
 
-        Camera {
-            aspect_ratio,
-            image_width,
-            samples_per_pixel,
-            max_depth,
-            vfov,
-            lookat,
-            lookfrom,
-            vup,
-            defocus_angle,
-            focus_dist,
-            background,
-            image_height,
+        View {
             center,
-            pixel00_loc,
-            delta_u,
-            delta_v,
+            look_dist: (lookat - lookfrom).norm(),
+            vfov,
+            viewport_upper_left,
             u,
             v,
             w,
+            delta_u,
+            delta_v,
             defocus_disk_u,
             defocus_disk_v,
+            defocus: defocus_angle > 0.,
         }
     }
 
-    pub fn render_to_channel(self: Arc<Self>, world: Arc<dyn Hittable + Send + Sync>) -> Receiver<PixelData> {
-        let (sender, receiver) = mpsc::channel();
+    /// Returns a random offset across the lens from its centre, which is zero without defocus
+    /// blur.
+    fn defocus_disk_sample(&self) -> Vector3<f64> {
+        if !self.defocus {
+            return Vector3::zeros();
+        }
+        let p = square_to_disk(sampling::get_2d());
+        (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v)
+    }
+}
 
-        thread::spawn(move || {
-            (0..self.image_width * self.image_height)
-                .into_par_iter()
-                .for_each_with(sender, |s, n| {
-                    let i = n as u32 % self.image_width;
-                    let j = n as u32 / self.image_width;
+impl Camera {
+    pub fn builder() -> CameraBuilder {
+        CameraBuilder::default()
+    }
+
+    pub fn new(settings: CameraBuilder) -> Camera {
+        let CameraBuilder {
+            aspect_ratio,
+            image_width,
+            vfov,
+            lookat,
+            lookfrom,
+            filter,
+            filter_radius,
+            ..
+        } = settings;
+
+        let image_height = ((image_width as f64 / aspect_ratio) as u32).max(1);
+
+        // A keyframed camera that holds still while the shutter is open sees the same view for
+        // every ray, so it is worked out once here rather than for each ray
+        let open = settings.frame as f64 + settings.shutter_open;
+        let close = settings.frame as f64 + settings.shutter_close;
+        let (view, path) = match CameraPath::new(settings.interpolation, &settings.keyframes) {
+            Some(path) if path.is_still(open, close) => {
+                (path.view(&settings, image_height, open), None)
+            }
+            path => (View::new(&settings, image_height, lookfrom, lookat, vfov), path),
+        };
+
+        let filter_sampler =
+            FilterSampler::new(filter, filter_radius.unwrap_or(filter.default_radius()));
+
+        Camera {
+            settings,
+            image_height,
+            view,
+            path,
+            filter_sampler,
+        }
+    }
+
+    /// Returns a [`CameraBuilder`] holding the settings this [`Camera`] was built from, so they
+    /// can be tweaked and a new camera built.
+    pub fn to_builder(&self) -> CameraBuilder {
+        self.settings.clone()
+    }
+
+    /// Returns the environment lighting the scene, if it has one rather than a background colour.
+    pub fn environment(&self) -> Option<&Arc<dyn Environment>> {
+        self.settings.environment.as_ref()
+    }
+
+    /// Returns the width of the rendered image in pixels.
+    pub fn image_width(&self) -> u32 {
+        self.settings.image_width
+    }
+
+    /// Returns the most samples taken for any pixel. Without adaptive sampling every pixel gets
+    /// this many.
+    pub fn samples_per_pixel(&self) -> u32 {
+        self.settings.samples_per_pixel
+    }
 
-                    let colour: Vector3<f64> = (0..self.samples_per_pixel)
-                        .map(|_| {
-                            let r = self.get_ray(i, j);
-                            self.ray_colour(&r, self.max_depth, &world)
-                        })
-                        .sum();
+    /// Returns the most times a ray is allowed to bounce.
+    pub fn max_depth(&self) -> u32 {
+        self.settings.max_depth
+    }
+
+    /// Returns the frame of the animation being rendered.
+    pub fn frame(&self) -> u32 {
+        self.settings.frame
+    }
+
+    /// Returns the height of the rendered image in pixels.
+    pub fn image_height(&self) -> u32 {
+        self.image_height
+    }
 
+    /// Starts rendering the scene on a background thread, sending each pixel once it has been
+    /// sampled enough. The work done is added to the job's counters as each pixel is finished.
+    pub fn render_to_channel(self: Arc<Self>, world: Arc<dyn Hittable + Send + Sync>) -> RenderJob {
+        RenderJob::spawn(move |job| {
+            let scene = Scene::new(world.as_ref(), true, self.settings.samples_per_pixel);
+            (0..self.settings.image_width * self.image_height)
+                .into_par_iter()
+                .for_each_with(job.sender().clone(), |s, n| {
+                    if !job.proceed() {
+                        return;
+                    }
+
+                    let i = n % self.settings.image_width;
+                    let j = n / self.settings.image_width;
+
+                    let (stats, aovs) = job
+                        .counters()
+                        .record(|| self.sample_pixel(i, j, Default::default(), &scene));
+                    let radiance = stats.mean();
                     let data = PixelData {
                         index: n,
-                        colour: self.make_colour(colour),
+                        colour: self.make_colour(radiance),
+                        radiance,
+                        pass: 1,
+                        aovs: aovs.mean(),
                     };
 
-                    s.send(data).expect("Failed to send pixel data")
+                    // The receiver only hangs up once the image is no longer wanted
+                    let _ = s.send(data);
                 });
+        })
+    }
+
+    /// Starts rendering the scene on a background thread one sample per pixel at a time. After
+    /// each pass over the whole image every pixel is sent again with the average of all passes
+    /// so far, so the image sharpens as it renders. With adaptive sampling, pixels that have
+    /// converged are skipped in later passes. Rendering ends after `samples_per_pixel` passes,
+    /// once every pixel has converged, or once the job is cancelled, in which case the pass in
+    /// progress is dropped. The work done is added to the job's counters as each sample is
+    /// taken.
+    pub fn render_progressive(
+        self: Arc<Self>,
+        world: Arc<dyn Hittable + Send + Sync>,
+    ) -> RenderJob {
+        RenderJob::spawn(move |job| {
+            let scene = Scene::new(world.as_ref(), true, self.settings.samples_per_pixel);
+            let width = self.settings.image_width;
+            let pixels = (width * self.image_height) as usize;
+            let mut stats = vec![(PixelStats::default(), AovStats::default()); pixels];
+
+            for pass in 1..=self.settings.samples_per_pixel {
+                let sampled = AtomicBool::new(false);
+                let cancelled = AtomicBool::new(false);
+                stats
+                    .par_iter_mut()
+                    .enumerate()
+                    .for_each(|(n, (stats, aovs))| {
+                        if self.done(stats) {
+                            return;
+                        }
+                        if !job.proceed() {
+                            cancelled.store(true, Ordering::Relaxed);
+                            return;
+                        }
+
+                        let i = n as u32 % width;
+                        let j = n as u32 / width;
+                        let (colour, weight, aov) = job
+                            .counters()
+                            .record(|| self.sample(i, j, stats.count(), &scene));
+                        stats.add(colour, weight);
+                        aovs.add(&aov, weight);
+                        sampled.store(true, Ordering::Relaxed);
+                    });
+
+                if cancelled.load(Ordering::Relaxed) || !sampled.load(Ordering::Relaxed) {
+                    return;
+                }
+
+                for (n, (stats, aovs)) in stats.iter().enumerate() {
+                    let radiance = stats.mean();
+                    let data = PixelData {
+                        index: n as u32,
+                        colour: self.make_colour(radiance),
+                        radiance,
+                        pass,
+                        aovs: aovs.mean(),
+                    };
+                    if job.sender().send(data).is_err() {
+                        return;
+                    }
+                }
+            }
+        })
+    }
+
+    /// Renders the scene in parallel into a [`FrameBuffer`] of unclamped linear radiance,
+    /// reporting progress with a terminal progress bar. Unlike [`Camera::render_to_channel`] this
+    /// needs no window, so it can be used on headless machines.
+    pub fn render_linear(&self, world: &dyn Hittable) -> FrameBuffer {
+        self.render_film(world, false, &RenderCounters::new()).0
+    }
+
+    /// Renders the scene with [`Camera::render_linear`], also recording what each pixel first
+    /// sees for every [`Aov`](crate::film::Aov).
+    pub fn render_with_aovs(&self, world: &dyn Hittable) -> (FrameBuffer, AovBuffer) {
+        let (frame, aovs) = self.render_film(world, true, &RenderCounters::new());
+        (frame, aovs.expect("AOVs were asked for"))
+    }
+
+    /// Renders the scene in parallel with a terminal progress bar, recording AOVs if `aovs` is
+    /// set and adding the work done to `counters`.
+    pub fn render_film(
+        &self,
+        world: &dyn Hittable,
+        aovs: bool,
+        counters: &RenderCounters,
+    ) -> (FrameBuffer, Option<AovBuffer>) {
+        let mut checkpoint = self.checkpoint(0, aovs);
+        self.render_from(world, &mut checkpoint, counters, Duration::MAX, &|_| {});
+        (checkpoint.frame(), checkpoint.aov_buffer())
+    }
+
+    /// Renders the scene like [`Camera::render_film`], carrying on from the samples already in
+    /// `checkpoint` until every pixel has `samples_per_pixel` samples or has converged. Every
+    /// `interval` the progress so far is passed to `save`, and once the render is done it is
+    /// left in `checkpoint`. Pixels being sampled when progress is saved count as not started,
+    /// so the interval should be long next to the time a pixel takes.
+    pub fn render_from(
+        &self,
+        world: &dyn Hittable,
+        checkpoint: &mut Checkpoint,
+        counters: &RenderCounters,
+        interval: Duration,
+        save: &(dyn Fn(&Checkpoint) + Sync),
+    ) {
+        let bar = ProgressBar::new((self.image_height * self.settings.image_width) as u64);
+        bar.set_style(
+            ProgressStyle::with_template(
+                "{elapsed_precise} [{wide_bar}] {percent}% ({eta} remaining)",
+            )
+            .unwrap(),
+        );
+
+        let scene = Scene::new(world, checkpoint.has_aovs(), checkpoint.sequence_samples());
+        let pixels: Vec<_> = (0..(checkpoint.width() * checkpoint.height()) as usize)
+            .map(|n| Mutex::new(checkpoint.pixel(n)))
+            .collect();
+        let snapshot = || {
+            let mut snapshot = checkpoint.clone();
+            for (n, pixel) in pixels.iter().enumerate() {
+                let (stats, aovs) = *pixel.lock().unwrap();
+                snapshot.set_pixel(n, stats, aovs);
+            }
+            snapshot
+        };
+        let last_saved = Mutex::new(Instant::now());
+
+        pixels.par_iter().enumerate().for_each(|(n, pixel)| {
+            let i = n as u32 % self.settings.image_width;
+            let j = n as u32 / self.settings.image_width;
+
+            let start = *pixel.lock().unwrap();
+            *pixel.lock().unwrap() = counters.record(|| self.sample_pixel(i, j, start, &scene));
+            bar.inc(1);
+
+            // Only one thread saves at a time, and the rest carry on rendering
+            if let Ok(mut last_saved) = last_saved.try_lock() {
+                if last_saved.elapsed() >= interval {
+                    save(&snapshot());
+                    *last_saved = Instant::now();
+                }
+            }
         });
 
-        receiver
-    }
-
-    // pub fn render_par(&self, world: &dyn Hittable) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
-    //     let bar = ProgressBar::new((self.image_height * self.image_width) as u64);
-    //     let mut img = ImageBuffer::new(self.image_width, self.image_height);
-    //     let mut pixels =
-    //         vec![Rgb([0 as u8, 0 as u8, 0 as u8]); (self.image_height * self.image_width) as usize];
-    //
-    //     (pixels).par_iter_mut().enumerate().for_each(|(n, pixel)| {
-    //         let i = n as u32 % self.image_width;
-    //         let j = n as u32 / self.image_width;
-    //
-    //         bar.inc(1);
-    //
-    //         let colour: Vector3<f64> = (0..self.samples_per_pixel)
-    //             .map(|_| {
-    //                 let r = self.get_ray(i, j);
-    //                 self.ray_colour(&r, self.max_depth, world)
-    //             })
-    //             .sum();
-    //
-    //         *pixel = self.make_colour(colour);
-    //     });
-    //
-    //     bar.finish();
-    //
-    //     for (x, y, pixel) in img.enumerate_pixels_mut() {
-    //         *pixel = pixels[(x + self.image_width * y) as usize];
-    //     }
-    //
-    //     img
-    // }
-
-    fn get_ray(&self, i: u32, j: u32) -> Ray {
-        let pixel_center =
-            self.pixel00_loc + ((i as f64) * self.delta_u) + ((j as f64) * self.delta_v);
-        let pixel_sample = pixel_center + self.pixel_sample_square();
-        let ray_origin = if self.defocus_angle <= 0. {
-            self.center
-        } else {
-            self.defocus_disk_sample()
+        bar.finish();
+        counters.finish();
+        *checkpoint = snapshot();
+    }
+
+    /// Renders the pixels in columns `xs` of rows `ys`, for an image rendered in parts, such as
+    /// by the workers of a distributed render. The statistics of each pixel are returned row by
+    /// row, with AOVs if `aovs` is set, and the work done is added to `counters`.
+    pub fn render_region(
+        &self,
+        world: &dyn Hittable,
+        xs: Range<u32>,
+        ys: Range<u32>,
+        aovs: bool,
+        counters: &RenderCounters,
+    ) -> Vec<(PixelStats, AovStats)> {
+        let scene = Scene::new(world, aovs, self.settings.samples_per_pixel);
+        let width = xs.len() as u32;
+        (0..width * ys.len() as u32)
+            .into_par_iter()
+            .map(|n| {
+                let i = xs.start + n % width;
+                let j = ys.start + n / width;
+                counters.record(|| self.sample_pixel(i, j, Default::default(), &scene))
+            })
+            .collect()
+    }
+
+    /// Starts a [`Checkpoint`] for rendering with this camera, recording AOVs if `aovs` is set.
+    /// `scene` is a fingerprint of the scene, which is checked when the render is carried on.
+    pub fn checkpoint(&self, scene: u64, aovs: bool) -> Checkpoint {
+        Checkpoint::new(
+            self.settings.image_width,
+            self.image_height,
+            scene,
+            self.fingerprint(),
+            self.settings.samples_per_pixel,
+            aovs,
+        )
+    }
+
+    /// Returns a fingerprint of the settings that decide what each sample of a pixel is.
+    /// Everything but the number of samples, adaptive sampling and tone mapping counts, as
+    /// those can change between a checkpoint and the render carrying it on.
+    pub fn fingerprint(&self) -> u64 {
+        let defaults = CameraBuilder::default();
+        let settings = CameraBuilder {
+            samples_per_pixel: defaults.samples_per_pixel,
+            min_samples_per_pixel: defaults.min_samples_per_pixel,
+            noise_threshold: defaults.noise_threshold,
+            tone_map: defaults.tone_map,
+            exposure: defaults.exposure,
+            white_point: defaults.white_point,
+            ..self.settings.clone()
         };
-        let ray_direction = pixel_sample - ray_origin;
-        let ray_time = random();
+        let text = toml::to_string(&settings).expect("camera settings can be written as TOML");
+        random::hash_bytes(text.as_bytes())
+    }
 
-        Ray::with_time(ray_origin, ray_direction, ray_time)
+    /// Renders the scene with [`Camera::render_linear`] and converts it to an 8-bit image.
+    pub fn render(&self, world: &dyn Hittable) -> RgbImage {
+        self.to_image(&self.render_linear(world))
     }
 
-    fn defocus_disk_sample(&self) -> Point3<f64> {
-        let p = rng_in_unit_disk();
-        self.center + (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v)
+    /// Converts linear radiance to an 8-bit image, tone mapped the same way as the pixels sent by
+    /// [`Camera::render_to_channel`].
+    pub fn to_image(&self, frame: &FrameBuffer) -> RgbImage {
+        ImageBuffer::from_fn(frame.width(), frame.height(), |x, y| {
+            self.make_colour(frame.get(x, y))
+        })
     }
 
-    fn pixel_sample_square(&self) -> Vector3<f64> {
-        let px: f64 = -0.5 * rand::random::<f64>();
-        let py: f64 = -0.5 * rand::random::<f64>();
+    /// Renders the scene and writes it to `path`. The image format is chosen from the file
+    /// extension: `.exr`, `.hdr` and `.pfm` keep the unclamped radiance as 32-bit floats, while
+    /// formats such as `.png` or `.jpg` are written as 8-bit colour.
+    pub fn render_to_file<P: AsRef<Path>>(&self, world: &dyn Hittable, path: P) -> ImageResult<()> {
+        let frame = self.render_linear(world);
+        self.save(&frame, path)
+    }
 
-        (px * self.delta_u) + (py * self.delta_v)
+    /// Writes a rendered [`FrameBuffer`] to `path`, choosing the format from the file extension
+    /// as [`Camera::render_to_file`] does.
+    pub fn save<P: AsRef<Path>>(&self, frame: &FrameBuffer, path: P) -> ImageResult<()> {
+        if FrameBuffer::is_float_format(&path) {
+            frame.save(path)
+        } else {
+            self.to_image(frame).save(path)
+        }
     }
 
-    fn ray_colour(&self, ray: &Ray, depth: u32, world: &Arc<dyn Hittable + Send + Sync>) -> Vector3<f64> {
-        if depth == 0 {
-            return vector!(0., 0., 0.);
+    /// Samples pixel (`i`, `j`) until it has `samples_per_pixel` samples or, with adaptive
+    /// sampling, until it has converged, carrying on from the samples already in `start`.
+    fn sample_pixel(
+        &self,
+        i: u32,
+        j: u32,
+        (mut stats, mut aovs): (PixelStats, AovStats),
+        scene: &Scene,
+    ) -> (PixelStats, AovStats) {
+        while !self.done(&stats) {
+            let (colour, weight, aov) = self.sample(i, j, stats.count(), scene);
+            stats.add(colour, weight);
+            aovs.add(&aov, weight);
         }
+        (stats, aovs)
+    }
 
-        let rec = world.hit(ray, Interval::new(0.001, f64::MAX));
+    /// Takes sample number `sample` of pixel (`i`, `j`), returning its colour, filter weight and
+    /// what it first hit. The random numbers it uses depend only on the seed, the pixel and the
+    /// sample number.
+    fn sample(&self, i: u32, j: u32, sample: u32, scene: &Scene) -> (Vector3<f64>, f64, AovSample) {
+        let CameraBuilder { seed, sampler, .. } = self.settings;
+        let pixel = i + j * self.settings.image_width;
+        random::seed_sample(seed, pixel, sample);
+        sampling::start_sample(sampler, scene.sequence_samples, seed, pixel, sample);
+
+        // The point on the image the sample is taken at, in pixels from the top left
+        let ([px, py], weight) = self.filter_sampler.sample(sampling::get_2d());
+        let x = i as f64 + 0.5 + px;
+        let y = j as f64 + 0.5 + py;
+
+        let mut aov = AovSample::default();
+        let colour = match self.get_ray(x, y) {
+            Some(r) => self.ray_colour(r, scene, &mut aov),
+            None => Vector3::zeros(),
+        };
+        (colour, weight, aov)
+    }
 
-        if rec.is_none() {
-            return self.background;
-        }
+    /// Returns whether a pixel needs no more samples.
+    fn done(&self, stats: &PixelStats) -> bool {
+        let CameraBuilder {
+            samples_per_pixel,
+            min_samples_per_pixel,
+            noise_threshold,
+            ..
+        } = self.settings;
+
+        stats.count() >= samples_per_pixel.max(1)
+            || (noise_threshold > 0.
+                && stats.count() >= min_samples_per_pixel.max(2)
+                && stats.converged(noise_threshold))
+    }
 
-        let rec = rec.unwrap();
+    /// Returns the ray through the point (`x`, `y`) on the image, measured in pixels from the
+    /// top left, or `None` if the projection does not cover that point.
+    fn get_ray(&self, x: f64, y: f64) -> Option<Ray> {
+        let CameraBuilder {
+            shutter_open,
+            shutter_close,
+            shutter_curve,
+            ..
+        } = self.settings;
+        let ray_time = self.settings.frame as f64
+            + shutter_open
+            + (shutter_close - shutter_open) * shutter_curve.sample(sampling::get_1d());
+
+        let keyframed;
+        let view = match &self.path {
+            Some(path) => {
+                keyframed = path.view(&self.settings, self.image_height, ray_time);
+                &keyframed
+            }
+            None => &self.view,
+        };
 
-        let colour_from_emmision = rec.mat.emitted(rec.u, rec.v, rec.point);
+        let (ray_origin, ray_direction) = match self.settings.projection {
+            Projection::Perspective => {
+                let pixel_sample = view.viewport_upper_left + x * view.delta_u + y * view.delta_v;
+                let ray_origin = view.center + view.defocus_disk_sample();
+                (ray_origin, pixel_sample - ray_origin)
+            }
+            Projection::Orthographic => {
+                // The viewport is scaled up from the focus distance to the distance of
+                // `lookat`, and each ray starts on the plane through the camera with its own
+                // lens centred straight back from the point it is focused on
+                let focus_dist = self.settings.focus_dist;
+                let scale = view.look_dist / focus_dist;
+                let pixel_sample = view.viewport_upper_left + x * view.delta_u + y * view.delta_v;
+                let lens_center =
+                    view.center + scale * (pixel_sample - view.center + focus_dist * view.w);
+                let ray_origin = lens_center + view.defocus_disk_sample();
+                (ray_origin, lens_center - focus_dist * view.w - ray_origin)
+            }
+            Projection::Fisheye => {
+                let half_height = self.image_height as f64 / 2.;
+                let nx = (x - self.settings.image_width as f64 / 2.) / half_height;
+                let ny = (y - half_height) / half_height;
+                let r = nx.hypot(ny);
+                if r > 1. {
+                    return None;
+                }
+                let theta = r * view.vfov.to_radians() / 2.;
+
+                let (sin_theta, cos_theta) = theta.sin_cos();
+                let across = if r > 0. {
+                    (nx * view.u - ny * view.v) / r
+                } else {
+                    Vector3::zeros()
+                };
+                (view.center, sin_theta * across - cos_theta * view.w)
+            }
+            Projection::Equirectangular => {
+                let longitude = (x / self.settings.image_width as f64 - 0.5) * 2. * PI;
+                let latitude = (0.5 - y / self.image_height as f64) * PI;
+
+                let (sin_lon, cos_lon) = longitude.sin_cos();
+                let (sin_lat, cos_lat) = latitude.sin_cos();
+                let direction = cos_lat * (sin_lon * view.u - cos_lon * view.w) + sin_lat * view.v;
+                (view.center, direction)
+            }
+        };
 
-        if let Some((scattered, attenuation)) = rec.mat.scatter(ray, &rec) {
-            let col = self.ray_colour(&scattered, depth - 1, world);
-            vector!(
-                col.x * attenuation.x + colour_from_emmision.x,
-                col.y * attenuation.y + colour_from_emmision.y,
-                col.z * attenuation.z + colour_from_emmision.z,
-            )
-        } else {
-            colour_from_emmision
+        Some(Ray::with_time(ray_origin, ray_direction, ray_time))
+    }
+
+    /// Returns the radiance arriving along `ray`, following it from surface to surface. The
+    /// light each surface adds is scaled by the throughput, how much of it survives the bounces
+    /// back to the camera. After `russian_roulette_depth` bounces paths carrying little light
+    /// are ended at random, and the ones that carry on are scaled up to make up for them. When
+    /// the scene records AOVs, what the ray first hits is written to `aov`.
+    fn ray_colour(&self, mut ray: Ray, scene: &Scene, aov: &mut AovSample) -> Vector3<f64> {
+        let Scene { world, lights, .. } = scene;
+        let mut colour = Vector3::zeros();
+        let mut throughput = vector![1., 1., 1.];
+        // The density the last surface picked `ray` with, if that surface also sampled the
+        // environment and `lights` directly. Light the ray then finds is weighted against
+        // having been sampled that way
+        let mut scatter_pdf = None;
+
+        for depth in 0..self.settings.max_depth {
+            if depth == 0 {
+                Counter::PrimaryRay.increment();
+            } else {
+                Counter::SecondaryRay.increment();
+            }
+            let Some(rec) = world.hit(&ray, Interval::new(0.001, f64::MAX)) else {
+                let escaped = match &self.settings.environment {
+                    Some(environment) => {
+                        let weight = match scatter_pdf {
+                            Some(pdf) => power_heuristic(pdf, environment.pdf(ray.direction())),
+                            None => 1.,
+                        };
+                        environment.radiance(ray.direction()) * weight
+                    }
+                    None => self.settings.background,
+                };
+                colour += throughput.component_mul(&escaped);
+                break;
+            };
+            Counter::PathVertex.increment();
+
+            if let (0, Some(materials)) = (depth, &scene.materials) {
+                *aov = AovSample {
+                    normal: rec.normal,
+                    albedo: rec.mat.albedo(&rec),
+                    depth: rec.t * ray.direction().norm(),
+                    position: rec.point,
+                    uv: [rec.u, rec.v],
+                    object_id: rec.object_id + 1,
+                    material_id: materials.id(rec.mat),
+                };
+            }
+
+            let mut colour_from_emmision = rec.mat.emitted(rec.u, rec.v, rec.point);
+            if let (Some(pdf), false) = (scatter_pdf, colour_from_emmision == Vector3::zeros()) {
+                let light_pdf = lights.pdf(ray.origin(), ray.direction(), *ray.time());
+                colour_from_emmision *= power_heuristic(pdf, light_pdf);
+            }
+            colour += throughput.component_mul(&colour_from_emmision);
+
+            // Surfaces that scatter light over many directions sample the environment and the
+            // lights directly, which finds small bright areas such as the sun far sooner than
+            // waiting for a bounce to happen upon them. Each sample is weighted against the
+            // chance of scattering having found it, so that whichever way finds a light more
+            // easily counts most
+            let sample_direct = !rec.mat.is_specular();
+            let mut direct = Vector3::zeros();
+            if let (Some(environment), true) = (&self.settings.environment, sample_direct) {
+                let (direction, pdf) = environment.sample(sampling::get_2d());
+                let f = rec.mat.eval(&ray, &rec, &direction);
+                if pdf > 0. && f != Vector3::zeros() {
+                    let shadow = Ray::with_time(rec.point, direction, *ray.time());
+                    Counter::ShadowRay.increment();
+                    if world.hit(&shadow, Interval::new(0.001, f64::MAX)).is_none() {
+                        let radiance = environment.radiance(&direction);
+                        let weight = power_heuristic(pdf, rec.mat.pdf(&ray, &rec, &direction));
+                        direct += f.component_mul(&radiance) * (weight / pdf);
+                    }
+                }
+            }
+            if !lights.is_empty() && sample_direct {
+                // Whatever the shadow ray reaches first is the light that arrives, even if that
+                // is not the light that was sampled
+                let direction = lights.sample(&rec.point, sampling::get_2d(), *ray.time());
+                let pdf = lights.pdf(&rec.point, &direction, *ray.time());
+                let f = rec.mat.eval(&ray, &rec, &direction);
+                if pdf > 0. && f != Vector3::zeros() {
+                    let shadow = Ray::with_time(rec.point, direction, *ray.time());
+                    Counter::ShadowRay.increment();
+                    if let Some(light) = world.hit(&shadow, Interval::new(0.001, f64::MAX)) {
+                        let radiance = light.mat.emitted(light.u, light.v, light.point);
+                        let weight = power_heuristic(pdf, rec.mat.pdf(&ray, &rec, &direction));
+                        direct += f.component_mul(&radiance) * (weight / pdf);
+                    }
+                }
+            }
+            colour += throughput.component_mul(&direct);
+
+            let Some(scatter) = rec.mat.scatter(&ray, &rec) else {
+                break;
+            };
+            throughput.component_mul_assign(&scatter.attenuation);
+
+            if depth >= self.settings.russian_roulette_depth {
+                let survival = throughput.max().min(1.);
+                if sampling::get_1d() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+
+            scatter_pdf = scatter.pdf.filter(|_| sample_direct);
+            ray = scatter.ray;
         }
+
+        colour
     }
 
+    /// Turns linear radiance into a display colour, applying the exposure, the tone map and
+    /// then the sRGB transfer curve.
     fn make_colour(&self, vec: Vector3<f64>) -> Rgb<u8> {
-        let scale = 1. / self.samples_per_pixel as f64;
+        let CameraBuilder {
+            tone_map,
+            exposure,
+            white_point,
+            ..
+        } = self.settings;
+
+        let exposed = vec * exposure.exp2();
+        let display = tone_map.apply(exposed, white_point);
+        let encode = |c: f64| (linear_to_srgb(c) * 255.).round() as u8;
+
+        Rgb([encode(display.x), encode(display.y), encode(display.z)])
+    }
+}
 
-        let r = vec.x * scale;
-        let g = vec.y * scale;
-        let b = vec.z * scale;
+/// The scene being rendered, along with what is found in it before rendering starts.
+struct Scene<'a> {
+    world: &'a dyn Hittable,
+    lights: LightList,
+    /// Only found when AOVs are being recorded.
+    materials: Option<MaterialIds>,
+    /// The samples per pixel the sampler is set up for, see [`Checkpoint`].
+    sequence_samples: u32,
+}
+
+impl<'a> Scene<'a> {
+    fn new(world: &'a dyn Hittable, aovs: bool, sequence_samples: u32) -> Scene<'a> {
+        Scene {
+            world,
+            lights: LightList::new(world),
+            materials: aovs.then(|| MaterialIds::new(world)),
+            sequence_samples,
+        }
+    }
+}
 
-        let intensity = Interval::new(0., 0.999);
+/// Returns the weight of a sample taken with density `pdf` when the same direction could also
+/// have been sampled with density `other`, using Veach's power heuristic.
+fn power_heuristic(pdf: f64, other: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other * other);
+    if a + b == 0. {
+        0.
+    } else {
+        a / (a + b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::point;
+
+    use super::*;
+
+    /// A camera at the origin looking down -z with y up, `width` pixels wide.
+    fn camera(projection: Projection, vfov: f64, width: u32, aspect_ratio: f64) -> Camera {
+        CameraBuilder::default()
+            .projection(projection)
+            .vfov(vfov)
+            .image_width(width)
+            .aspect_ratio(aspect_ratio)
+            .lookfrom(point![0., 0., 0.])
+            .lookat(point![0., 0., -1.])
+            .vup(vector![0., 1., 0.])
+            .defocus_angle(0.)
+            .focus_dist(1.)
+            .build()
+    }
+
+    fn direction(cam: &Camera, x: f64, y: f64) -> Vector3<f64> {
+        cam.get_ray(x, y).unwrap().direction().normalize()
+    }
+
+    fn assert_close(a: Vector3<f64>, b: Vector3<f64>) {
+        assert!((a - b).norm() < 1e-9, "{a} is not {b}");
+    }
+
+    #[test]
+    fn perspective_rays_spread_over_the_field_of_view() {
+        let cam = camera(Projection::Perspective, 90., 100, 1.);
+        assert_close(direction(&cam, 50., 50.), vector![0., 0., -1.]);
+        // Half of 90 degrees up from the middle of the top edge
+        assert_close(direction(&cam, 50., 0.), vector![0., 1., -1.].normalize());
+        assert_close(direction(&cam, 100., 50.), vector![1., 0., -1.].normalize());
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel_and_cover_the_view_at_lookat() {
+        let cam = camera(Projection::Orthographic, 90., 100, 1.);
+        for (x, y) in [(0., 0.), (50., 50.), (100., 30.)] {
+            assert_close(direction(&cam, x, y), vector![0., 0., -1.]);
+        }
+        // A perspective camera with a 90 degree view sees one unit either side at `lookat`
+        let corner = cam.get_ray(0., 0.).unwrap();
+        assert_close(corner.origin().coords, vector![-1., 1., 0.]);
+    }
+
+    #[test]
+    fn fisheye_rays_turn_in_step_with_distance_from_the_centre() {
+        let cam = camera(Projection::Fisheye, 180., 100, 1.);
+        assert_close(direction(&cam, 50., 50.), vector![0., 0., -1.]);
+        assert_close(direction(&cam, 50., 0.), vector![0., 1., 0.]);
+        assert_close(direction(&cam, 75., 50.), vector![1., 0., -1.].normalize());
+        // Outside the circle nothing is seen
+        assert!(cam.get_ray(0., 0.).is_none());
+    }
 
-        let col = Rgb([
-            (intensity.clamp(r.sqrt()) * 255.) as u8,
-            (intensity.clamp(g.sqrt()) * 255.) as u8,
-            (intensity.clamp(b.sqrt()) * 255.) as u8,
-        ]);
-        col
+    #[test]
+    fn equirectangular_rays_cover_the_whole_sphere() {
+        let cam = camera(Projection::Equirectangular, 90., 200, 2.);
+        assert_close(direction(&cam, 100., 50.), vector![0., 0., -1.]);
+        assert_close(direction(&cam, 150., 50.), vector![1., 0., 0.]);
+        assert_close(direction(&cam, 0., 50.), vector![0., 0., 1.]);
+        assert_close(direction(&cam, 100., 0.), vector![0., 1., 0.]);
     }
 }
@@ -1,19 +1,64 @@
+use std::{iter, sync::Arc};
+
 use nalgebra::{point, vector, Point3, Vector3};
+use serde::{Deserialize, Serialize};
 
-use super::Camera;
+use super::{Camera, Projection, ShutterCurve};
+use crate::{
+    animation::Interpolation,
+    film::{Filter, ToneMap},
+    lights::Environment,
+    sampling::SamplerKind,
+};
 
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct CameraBuilder {
-    aspect_ratio: f64,
-    image_width: u32,
-    samples_per_pixel: u32,
-    max_depth: u32,
-    vfov: f64,
-    lookat: Point3<f64>,
-    lookfrom: Point3<f64>,
-    vup: Vector3<f64>,
-    defocus_angle: f64,
-    focus_dist: f64,
-    background: Vector3<f64>,
+    pub(super) aspect_ratio: f64,
+    pub(super) image_width: u32,
+    pub(super) samples_per_pixel: u32,
+    pub(super) min_samples_per_pixel: u32,
+    pub(super) noise_threshold: f64,
+    pub(super) seed: u64,
+    pub(super) sampler: SamplerKind,
+    pub(super) filter: Filter,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) filter_radius: Option<f64>,
+    pub(super) tone_map: ToneMap,
+    pub(super) exposure: f64,
+    pub(super) white_point: f64,
+    pub(super) max_depth: u32,
+    pub(super) russian_roulette_depth: u32,
+    pub(super) projection: Projection,
+    pub(super) vfov: f64,
+    pub(super) lookat: Point3<f64>,
+    pub(super) lookfrom: Point3<f64>,
+    pub(super) vup: Vector3<f64>,
+    pub(super) defocus_angle: f64,
+    pub(super) focus_dist: f64,
+    pub(super) background: Vector3<f64>,
+    /// Replaces `background` when set. It is written to scene files on its own rather than as
+    /// part of the camera.
+    #[serde(skip)]
+    pub(super) environment: Option<Arc<dyn Environment>>,
+    pub(super) shutter_open: f64,
+    pub(super) shutter_close: f64,
+    pub(super) shutter_curve: ShutterCurve,
+    pub(super) frame: u32,
+    pub(super) interpolation: Interpolation,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(super) keyframes: Vec<CameraKeyframe>,
+}
+
+/// Where the camera is at one moment. Between keyframes the camera moves as set by the
+/// builder's `interpolation`, and before the first or after the last it holds still.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraKeyframe {
+    pub time: f64,
+    pub lookfrom: Point3<f64>,
+    pub lookat: Point3<f64>,
+    pub vfov: f64,
 }
 
 impl Default for CameraBuilder {
@@ -22,7 +67,18 @@ impl Default for CameraBuilder {
             aspect_ratio: 1.,
             image_width: 100,
             samples_per_pixel: 1,
+            min_samples_per_pixel: 16,
+            noise_threshold: 0.,
+            seed: 0,
+            sampler: SamplerKind::Independent,
+            filter: Filter::Box,
+            filter_radius: None,
+            tone_map: ToneMap::Clamp,
+            exposure: 0.,
+            white_point: 4.,
             max_depth: 1,
+            russian_roulette_depth: 3,
+            projection: Projection::Perspective,
             vfov: 90.,
             lookat: point![0., 0., 0.],
             lookfrom: point![0., 0., 0.],
@@ -30,6 +86,13 @@ impl Default for CameraBuilder {
             defocus_angle: 0.,
             focus_dist: 10.,
             background: vector![0., 0., 0.],
+            environment: None,
+            shutter_open: 0.,
+            shutter_close: 1.,
+            shutter_curve: ShutterCurve::Box,
+            frame: 0,
+            interpolation: Interpolation::Linear,
+            keyframes: vec![],
         }
     }
 }
@@ -53,12 +116,81 @@ impl CameraBuilder {
         self
     }
 
+    // Setter for `min_samples_per_pixel`, the samples every pixel gets before adaptive sampling
+    // may stop early
+    pub fn min_samples_per_pixel(mut self, min_samples_per_pixel: u32) -> Self {
+        self.min_samples_per_pixel = min_samples_per_pixel;
+        self
+    }
+
+    // Setter for `noise_threshold`. When above zero, pixels stop being sampled once their noise
+    // falls below it, so `samples_per_pixel` becomes the most any pixel gets
+    pub fn noise_threshold(mut self, noise_threshold: f64) -> Self {
+        self.noise_threshold = noise_threshold;
+        self
+    }
+
+    // Setter for `seed`. Renders with the same seed and settings give identical images
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // Setter for `sampler`, which chooses how the random decisions for each sample are spread
+    pub fn sampler(mut self, sampler: SamplerKind) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    // Setter for `filter`, the pixel reconstruction filter
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    // Setter for `filter_radius` in pixels. Without it the filter's usual radius is used
+    pub fn filter_radius(mut self, filter_radius: f64) -> Self {
+        self.filter_radius = Some(filter_radius);
+        self
+    }
+
+    // Setter for `tone_map`, the curve that brings radiance into the range a display can show
+    pub fn tone_map(mut self, tone_map: ToneMap) -> Self {
+        self.tone_map = tone_map;
+        self
+    }
+
+    // Setter for `exposure` in stops, each of which doubles the brightness before tone mapping
+    pub fn exposure(mut self, exposure: f64) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    // Setter for `white_point`, the luminance shown as white by extended Reinhard
+    pub fn white_point(mut self, white_point: f64) -> Self {
+        self.white_point = white_point;
+        self
+    }
+
     // Setter for `max_depth`
     pub fn max_depth(mut self, max_depth: u32) -> Self {
         self.max_depth = max_depth;
         self
     }
 
+    // Setter for `russian_roulette_depth`, the bounces every path gets before it may be ended
+    // at random once it carries little light
+    pub fn russian_roulette_depth(mut self, russian_roulette_depth: u32) -> Self {
+        self.russian_roulette_depth = russian_roulette_depth;
+        self
+    }
+
+    // Setter for `projection`
+    pub fn projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
     // Setter for `vfov`
     pub fn vfov(mut self, vfov: f64) -> Self {
         self.vfov = vfov;
@@ -100,19 +232,100 @@ impl CameraBuilder {
         self.background = background;
         self
     }
+
+    // Setter for `environment`, which lights the scene in place of `background`
+    pub fn environment(mut self, environment: Arc<dyn Environment>) -> Self {
+        self.environment = Some(environment);
+        self
+    }
+
+    // Setter for `shutter_open`. Each ray is given a time while the shutter is open, which
+    // moving objects use to decide where they are
+    pub fn shutter_open(mut self, shutter_open: f64) -> Self {
+        self.shutter_open = shutter_open;
+        self
+    }
+
+    // Setter for `shutter_close`
+    pub fn shutter_close(mut self, shutter_close: f64) -> Self {
+        self.shutter_close = shutter_close;
+        self
+    }
+
+    // Setter for `shutter_curve`
+    pub fn shutter_curve(mut self, shutter_curve: ShutterCurve) -> Self {
+        self.shutter_curve = shutter_curve;
+        self
+    }
+
+    // Setter for `frame`, the frame of an animation being rendered. Times are counted in
+    // frames, so the shutter opens and closes this many frames later than it is set to
+    pub fn frame(mut self, frame: u32) -> Self {
+        self.frame = frame;
+        self
+    }
+
+    // Setter for `interpolation`, how the camera moves between keyframes
+    pub fn interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    // Adds a keyframe. Once there are keyframes, they decide `lookfrom`, `lookat` and `vfov`
+    pub fn keyframe(
+        mut self,
+        time: f64,
+        lookfrom: Point3<f64>,
+        lookat: Point3<f64>,
+        vfov: f64,
+    ) -> Self {
+        self.keyframes.push(CameraKeyframe {
+            time,
+            lookfrom,
+            lookat,
+            vfov,
+        });
+        self
+    }
+
+    /// Checks the settings whose types allow values the camera can't use, returning the name of
+    /// the first bad one and what is wrong with it.
+    pub fn check(&self) -> Result<(), (&'static str, String)> {
+        if self.image_width == 0 {
+            let message = "expected at least one pixel, got `0`".to_string();
+            return Err(("image_width", message));
+        }
+        check_positive(self.aspect_ratio).map_err(|e| ("aspect_ratio", e))?;
+        // A perspective view can't see as far round as straight out to the side, but a fisheye
+        // can see all the way behind it
+        let (fits, expected): (fn(f64) -> bool, _) = match self.projection {
+            Projection::Fisheye => (|vfov| vfov > 0. && vfov <= 360., "up to 360"),
+            _ => (|vfov| vfov > 0. && vfov < 180., "below 180"),
+        };
+        let fovs = iter::once(self.vfov).chain(self.keyframes.iter().map(|k| k.vfov));
+        for vfov in fovs {
+            if !fits(vfov) {
+                let message = format!("expected an angle above 0 and {expected}, got `{vfov}`");
+                return Err(("vfov", message));
+            }
+        }
+        if let Some(filter_radius) = self.filter_radius {
+            check_positive(filter_radius).map_err(|e| ("filter_radius", e))?;
+        }
+        check_positive(self.white_point).map_err(|e| ("white_point", e))?;
+        Ok(())
+    }
+
     pub fn build(self) -> Camera {
-        Camera::new(
-            self.aspect_ratio,
-            self.image_width,
-            self.samples_per_pixel,
-            self.max_depth,
-            self.vfov,
-            self.lookat,
-            self.lookfrom,
-            self.vup,
-            self.defocus_angle,
-            self.focus_dist,
-            self.background,
-        )
+        Camera::new(self)
+    }
+}
+
+/// Checks that `value` is a finite number above zero, as sizes and ratios must be.
+pub fn check_positive(value: f64) -> Result<f64, String> {
+    if value.is_finite() && value > 0. {
+        Ok(value)
+    } else {
+        Err(format!("expected a positive number, got `{value}`"))
     }
 }
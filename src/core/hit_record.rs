@@ -13,6 +13,10 @@ pub struct HitRecord<'a> {
     pub u: f64,
     pub v: f64,
     pub front_face: bool,
+    /// The index of the object hit among those the scene's [`BvhNode`] was built from.
+    ///
+    /// [`BvhNode`]: crate::shapes::BvhNode
+    pub object_id: u32,
 }
 
 impl HitRecord<'_> {
@@ -38,6 +42,7 @@ impl HitRecord<'_> {
             u,
             v,
             front_face,
+            object_id: 0,
         }
     }
 }
@@ -1,9 +1,29 @@
 
+use std::any::Any;
+
+use nalgebra::{vector, Point3, Vector3};
+
 use crate::{shapes::Aabb, utility::Interval};
 
 use super::{hit_record::HitRecord, Ray};
 
 pub trait Hittable: Send + Sync {
-    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>>;
     fn bounding_box(&self) -> &Aabb;
+
+    /// Returns the density over solid angle with which [`Hittable::random`] picks `direction`
+    /// from `origin` at `time`. Objects that cannot be sampled this way return zero.
+    fn pdf_value(&self, _origin: &Point3<f64>, _direction: &Vector3<f64>, _time: f64) -> f64 {
+        0.
+    }
+
+    /// Maps a point in the unit square to a direction from `origin` towards this object at
+    /// `time`, which is how light is sampled from emissive objects.
+    fn random(&self, _origin: &Point3<f64>, _u: [f64; 2], _time: f64) -> Vector3<f64> {
+        vector![1., 0., 0.]
+    }
+
+    /// Returns this object as [`Any`] so that its concrete type can be recovered, which is how
+    /// scenes are exported.
+    fn as_any(&self) -> &dyn Any;
 }
@@ -1,10 +1,12 @@
-use std::sync::Arc;
+use std::{any::Any, sync::Arc};
 
+use nalgebra::{vector, Point3, Vector3};
 
 use crate::{shapes::Aabb, utility::Interval};
 
 use super::{hit_record::HitRecord, Hittable, Ray};
 
+#[derive(Default)]
 pub struct HittableList {
     pub objects: Vec<Arc<dyn Hittable>>,
     bbox: Aabb,
@@ -17,18 +19,18 @@ impl HittableList {
 
     pub fn add_list(&mut self, objects: HittableList) {
         for obj in objects.objects {
-            self.bbox = self.bbox.merge(&obj.bounding_box());
+            self.bbox = self.bbox.merge(obj.bounding_box());
             self.objects.push(obj);
         }
     }
     pub fn add(&mut self, object: Box<dyn Hittable>) {
-        self.bbox = self.bbox.merge(&object.bounding_box());
+        self.bbox = self.bbox.merge(object.bounding_box());
         self.objects.push(object.into());
     }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
         let mut best_rec = None;
         let mut hit_anything = false;
         let mut closest = ray_t;
@@ -51,4 +53,31 @@ impl Hittable for HittableList {
     fn bounding_box(&self) -> &Aabb {
         &self.bbox
     }
+
+    fn pdf_value(&self, origin: &Point3<f64>, direction: &Vector3<f64>, time: f64) -> f64 {
+        if self.objects.is_empty() {
+            return 0.;
+        }
+        let sum: f64 = self
+            .objects
+            .iter()
+            .map(|obj| obj.pdf_value(origin, direction, time))
+            .sum();
+        sum / self.objects.len() as f64
+    }
+
+    fn random(&self, origin: &Point3<f64>, [u1, u2]: [f64; 2], time: f64) -> Vector3<f64> {
+        if self.objects.is_empty() {
+            return vector![1., 0., 0.];
+        }
+
+        // Pick an object uniformly, then reuse what is left of `u1` to sample it
+        let scaled = u1 * self.objects.len() as f64;
+        let index = (scaled as usize).min(self.objects.len() - 1);
+        self.objects[index].random(origin, [scaled - index as f64, u2], time)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
@@ -0,0 +1,86 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    materials::{Material, Texture},
+    shapes::{BvhNode, Quad, Sphere},
+    wrappers::{ConstantMedium, RotateY, Translate},
+};
+
+use super::{Hittable, HittableList};
+
+/// Numbers the distinct materials in a scene, counting from one in the order they are first
+/// found. Materials are the same if they share a texture or, for metal and glass, if they have
+/// the same values, which is what cloning one material across objects gives.
+#[derive(Default)]
+pub struct MaterialIds {
+    ids: HashMap<Key, u32>,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum Key {
+    Lambertian(usize),
+    Metal([u64; 4]),
+    Dielectric(u64),
+    DiffuseLight(usize),
+    Isotropic(usize),
+}
+
+impl MaterialIds {
+    /// Finds every material in `world`.
+    pub fn new(world: &dyn Hittable) -> MaterialIds {
+        let mut ids = MaterialIds::default();
+        ids.gather(world);
+        ids
+    }
+
+    /// Returns the number of `material`, or zero if it is not in the scene.
+    pub fn id(&self, material: &Material) -> u32 {
+        self.ids.get(&key(material)).copied().unwrap_or(0)
+    }
+
+    fn add(&mut self, material: &Material) {
+        let next = self.ids.len() as u32 + 1;
+        self.ids.entry(key(material)).or_insert(next);
+    }
+
+    fn gather(&mut self, object: &dyn Hittable) {
+        let any = object.as_any();
+        if let Some(list) = any.downcast_ref::<HittableList>() {
+            for object in &list.objects {
+                self.gather(object.as_ref());
+            }
+        } else if let Some(node) = any.downcast_ref::<BvhNode>() {
+            let (left, right) = node.children();
+            self.gather(left.as_ref());
+            if !Arc::ptr_eq(left, right) {
+                self.gather(right.as_ref());
+            }
+        } else if let Some(quad) = any.downcast_ref::<Quad>() {
+            self.add(quad.material());
+        } else if let Some(sphere) = any.downcast_ref::<Sphere>() {
+            self.add(sphere.material());
+        } else if let Some(translate) = any.downcast_ref::<Translate>() {
+            self.gather(translate.object().as_ref());
+        } else if let Some(rotate) = any.downcast_ref::<RotateY>() {
+            self.gather(rotate.object().as_ref());
+        } else if let Some(medium) = any.downcast_ref::<ConstantMedium>() {
+            self.add(medium.phase_function());
+        }
+    }
+}
+
+fn key(material: &Material) -> Key {
+    let address = |texture: &Arc<dyn Texture>| Arc::as_ptr(texture) as *const () as usize;
+    match material {
+        Material::Lambertian { albedo } => Key::Lambertian(address(albedo)),
+        Material::Metal { albedo, fuzz } => Key::Metal([
+            albedo.x.to_bits(),
+            albedo.y.to_bits(),
+            albedo.z.to_bits(),
+            fuzz.to_bits(),
+        ]),
+        Material::Dielectric { ir } => Key::Dielectric(ir.to_bits()),
+        Material::DiffuseLight { emit } => Key::DiffuseLight(address(emit)),
+        Material::Isotropic { albedo } => Key::Isotropic(address(albedo)),
+    }
+}
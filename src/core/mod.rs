@@ -2,12 +2,22 @@ mod camera;
 mod hit_record;
 mod hittable;
 mod hittable_list;
+mod material_ids;
+mod projection;
+mod render_job;
+mod render_stats;
+mod shutter;
 mod ray;
 mod camera_builder;
 
 pub use camera::{Camera, PixelData};
 pub use hit_record::HitRecord;
-pub use camera_builder::CameraBuilder;
+pub use camera_builder::{check_positive, CameraBuilder, CameraKeyframe};
 pub use hittable::Hittable;
 pub use hittable_list::HittableList;
+pub use material_ids::MaterialIds;
+pub use projection::Projection;
+pub use render_job::{JobContext, JobStatus, RenderJob};
+pub use render_stats::{Counter, RenderCounters, RenderStats};
+pub use shutter::ShutterCurve;
 pub use ray::Ray;
@@ -0,0 +1,21 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// How a [`super::Camera`] maps points on the image to directions in the scene.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Projection {
+    /// A thin lens camera, with `vfov` as the angle across the image height
+    #[default]
+    Perspective,
+    /// Parallel rays, showing the same area of the scene as the perspective camera would at the
+    /// distance of `lookat`. Lines that are parallel in the scene stay parallel in the image
+    Orthographic,
+    /// An equidistant fisheye, where the angle from the view direction grows in step with the
+    /// distance from the image centre. The image is a circle touching the top and bottom, and
+    /// `vfov` is the angle across it, up to 360 to see all the way round
+    Fisheye,
+    /// A latitude-longitude panorama of the whole sphere around the camera, best rendered with
+    /// an aspect ratio of 2:1
+    Equirectangular,
+}
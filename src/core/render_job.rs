@@ -0,0 +1,286 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use rayon::ThreadPoolBuilder;
+
+use super::{PixelData, RenderCounters};
+
+/// Where a [`RenderJob`] has got to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum JobStatus {
+    /// Pixels are being rendered
+    Running,
+    /// The job is waiting to be resumed, having finished the pixels it had started
+    Paused,
+    /// The job was stopped before it finished
+    Cancelled,
+    /// Every pixel has been rendered
+    Finished,
+    /// The work panicked before it finished
+    Failed,
+}
+
+impl JobStatus {
+    const ALL: [JobStatus; 5] = [
+        JobStatus::Running,
+        JobStatus::Paused,
+        JobStatus::Cancelled,
+        JobStatus::Finished,
+        JobStatus::Failed,
+    ];
+}
+
+/// What a job and the thread doing its work share.
+struct Shared {
+    /// The [`JobStatus`] as a number. Every pixel checks it, so it is read without locking and
+    /// only a paused job takes the lock to wait.
+    status: AtomicU8,
+    /// Held while the status changes, so a job about to wait can't miss the change.
+    lock: Mutex<()>,
+    /// Signalled whenever `status` changes, to wake a paused job.
+    changed: Condvar,
+    counters: RenderCounters,
+}
+
+impl Shared {
+    fn status(&self) -> JobStatus {
+        JobStatus::ALL[self.status.load(Ordering::Acquire) as usize]
+    }
+
+    /// Changes the status from `from` to `to`, leaving it alone if it is anything else.
+    fn transition(&self, from: &[JobStatus], to: JobStatus) {
+        let _lock = self.lock.lock().unwrap();
+        if from.contains(&self.status()) {
+            self.status.store(to as u8, Ordering::Release);
+            self.changed.notify_all();
+        }
+    }
+}
+
+/// A render running on a background thread. Pixels are sent to [`RenderJob::receiver`] as they
+/// are finished, and the job can be paused, resumed and cancelled while it runs. Dropping a
+/// job cancels it and waits for the thread to stop, so no work carries on once the image is no
+/// longer wanted.
+///
+/// Each job has its own rayon thread pool, so the threads a paused job holds waiting can't hold
+/// up anything else run in parallel, such as denoising the pixels it has sent.
+pub struct RenderJob {
+    shared: Arc<Shared>,
+    receiver: Receiver<PixelData>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RenderJob {
+    /// Starts `work` on a new thread, where parallel iterators run on the job's own thread pool.
+    /// It is handed a [`JobContext`] to send pixels through and to check with before starting
+    /// each one. The job is finished once `work` returns, unless it was cancelled first, and has
+    /// failed if `work` panics.
+    pub fn spawn<F>(work: F) -> RenderJob
+    where
+        F: FnOnce(&JobContext) + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let shared = Arc::new(Shared {
+            status: AtomicU8::new(JobStatus::Running as u8),
+            lock: Mutex::new(()),
+            changed: Condvar::new(),
+            counters: RenderCounters::new(),
+        });
+
+        let context = JobContext {
+            shared: shared.clone(),
+            sender,
+        };
+        let thread = thread::spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                let pool = ThreadPoolBuilder::new()
+                    .build()
+                    .expect("the render threads could be started");
+                pool.install(|| work(&context));
+            }));
+            context.shared.counters.finish();
+            let (from, to) = match result {
+                Ok(()) => (
+                    &[JobStatus::Running, JobStatus::Paused][..],
+                    JobStatus::Finished,
+                ),
+                // Even a cancelled job failed if it panicked on the way out
+                Err(_) => (
+                    &[JobStatus::Running, JobStatus::Paused, JobStatus::Cancelled][..],
+                    JobStatus::Failed,
+                ),
+            };
+            context.shared.transition(from, to);
+        });
+
+        RenderJob {
+            shared,
+            receiver,
+            thread: Some(thread),
+        }
+    }
+
+    /// Returns the receiver that finished pixels arrive on. It disconnects once the job has
+    /// stopped, whether it finished or not.
+    pub fn receiver(&self) -> &Receiver<PixelData> {
+        &self.receiver
+    }
+
+    /// Returns the work done so far.
+    pub fn counters(&self) -> &RenderCounters {
+        &self.shared.counters
+    }
+
+    pub fn status(&self) -> JobStatus {
+        self.shared.status()
+    }
+
+    /// Stops the job from starting any more pixels. Pixels already started are still sent.
+    pub fn pause(&self) {
+        self.shared
+            .transition(&[JobStatus::Running], JobStatus::Paused);
+    }
+
+    /// Lets a paused job carry on.
+    pub fn resume(&self) {
+        self.shared
+            .transition(&[JobStatus::Paused], JobStatus::Running);
+    }
+
+    /// Stops the job for good. The pixels already started are still finished, so the thread
+    /// may take a moment to stop.
+    pub fn cancel(&self) {
+        self.shared.transition(
+            &[JobStatus::Running, JobStatus::Paused],
+            JobStatus::Cancelled,
+        );
+    }
+
+    /// Waits for the job to stop and returns how it ended. A paused job will wait forever unless
+    /// it is resumed or cancelled from another thread.
+    pub fn join(mut self) -> JobStatus {
+        if let Some(thread) = self.thread.take() {
+            // Panics in the work are caught and become the failed status
+            let _ = thread.join();
+        }
+        self.status()
+    }
+}
+
+impl Drop for RenderJob {
+    fn drop(&mut self) {
+        self.cancel();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The side of a [`RenderJob`] seen by the thread doing its work.
+pub struct JobContext {
+    shared: Arc<Shared>,
+    sender: Sender<PixelData>,
+}
+
+impl JobContext {
+    /// Returns whether to start another pixel, waiting first while the job is paused. Once this
+    /// returns false the job has been cancelled and the work should stop.
+    pub fn proceed(&self) -> bool {
+        let shared = &self.shared;
+        if shared.status() == JobStatus::Paused {
+            let mut lock = shared.lock.lock().unwrap();
+            while shared.status() == JobStatus::Paused {
+                lock = shared.changed.wait(lock).unwrap();
+            }
+        }
+        shared.status() != JobStatus::Cancelled
+    }
+
+    /// Returns the sender finished pixels go to.
+    pub fn sender(&self) -> &Sender<PixelData> {
+        &self.sender
+    }
+
+    /// Returns the counters to record the work done with.
+    pub fn counters(&self) -> &RenderCounters {
+        &self.shared.counters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use image::Rgb;
+    use rayon::prelude::*;
+
+    use super::*;
+
+    const PIXELS: u32 = 500;
+
+    /// Starts a job that sends `PIXELS` pixels, taking a moment over each.
+    fn slow_job() -> RenderJob {
+        RenderJob::spawn(|job| {
+            (0..PIXELS)
+                .into_par_iter()
+                .for_each_with(job.sender().clone(), |s, index| {
+                    if !job.proceed() {
+                        return;
+                    }
+                    thread::sleep(Duration::from_millis(2));
+                    let data = PixelData {
+                        index,
+                        colour: Rgb([0; 3]),
+                        radiance: Default::default(),
+                        pass: 1,
+                        aovs: Default::default(),
+                    };
+                    s.send(data).unwrap();
+                });
+        })
+    }
+
+    #[test]
+    fn paused_job_starts_no_pixels_and_leaves_the_global_pool_free() {
+        let job = slow_job();
+        job.pause();
+        // Let the pixels already started finish
+        thread::sleep(Duration::from_millis(100));
+        let before = job.receiver().try_iter().count() as u32;
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(job.receiver().try_iter().count(), 0);
+        assert_eq!(job.status(), JobStatus::Paused);
+
+        // Work on the global pool, like denoising the preview, still runs
+        let sum: u32 = (0..100u32).into_par_iter().sum();
+        assert_eq!(sum, 4950);
+
+        job.resume();
+        // The receiver disconnects once the job stops
+        let after = job.receiver().iter().count() as u32;
+        assert_eq!(before + after, PIXELS);
+        assert_eq!(job.join(), JobStatus::Finished);
+    }
+
+    #[test]
+    fn cancelled_job_stops_early() {
+        let job = slow_job();
+        job.pause();
+        job.cancel();
+        assert!((job.receiver().iter().count() as u32) < PIXELS);
+        assert_eq!(job.join(), JobStatus::Cancelled);
+    }
+
+    #[test]
+    fn panicking_job_fails() {
+        let job = RenderJob::spawn(|_| panic!("the work went wrong"));
+        assert_eq!(job.join(), JobStatus::Failed);
+    }
+}
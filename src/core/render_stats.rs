@@ -0,0 +1,227 @@
+use std::{
+    cell::Cell,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+/// Something counted while rendering.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Counter {
+    /// A ray from the camera
+    PrimaryRay,
+    /// A ray scattered from a surface
+    SecondaryRay,
+    /// A ray testing whether a sampled light can be seen
+    ShadowRay,
+    /// A BVH node whose bounding box was tested
+    NodeVisited,
+    /// An object at the bottom of the BVH that was tested
+    PrimitiveTest,
+    /// A surface a path hit
+    PathVertex,
+}
+
+const COUNTERS: usize = 6;
+
+thread_local! {
+    /// This thread's counts for the work being recorded by [`RenderCounters::record`]. Counting
+    /// here costs about as much as a plain addition, where counting straight into shared
+    /// atomics would have every thread fighting over the same cache lines.
+    static COUNTS: [Cell<u64>; COUNTERS] = const { [const { Cell::new(0) }; COUNTERS] };
+}
+
+impl Counter {
+    /// Adds one to this counter on the current thread. The count is only seen if it is made
+    /// within [`RenderCounters::record`].
+    #[inline]
+    pub fn increment(self) {
+        COUNTS.with(|counts| {
+            let count = &counts[self as usize];
+            count.set(count.get() + 1);
+        });
+    }
+}
+
+/// The totals of each [`Counter`] for a render, gathered from every thread that works on it.
+pub struct RenderCounters {
+    counts: [AtomicU64; COUNTERS],
+    start: Instant,
+    /// How long the render took, once it has finished.
+    time: OnceLock<Duration>,
+}
+
+impl RenderCounters {
+    /// Creates counters for a render that starts now.
+    pub fn new() -> RenderCounters {
+        RenderCounters {
+            counts: Default::default(),
+            start: Instant::now(),
+            time: OnceLock::new(),
+        }
+    }
+
+    /// Runs `f`, adding everything it counts on the current thread to these counters once it
+    /// returns. Renders record each pixel this way, so the counts of a pixel always go to the
+    /// render it belongs to, even when another render's work runs on the same thread.
+    pub fn record<T>(&self, f: impl FnOnce() -> T) -> T {
+        let _recording = Recording {
+            counters: self,
+            outer: COUNTS.with(|counts| counts.each_ref().map(Cell::take)),
+        };
+        f()
+    }
+
+    /// Stops the clock. Only the first call has any effect.
+    pub fn finish(&self) {
+        self.time.get_or_init(|| self.start.elapsed());
+    }
+
+    /// Returns the totals recorded so far, timed up to now if the render has not finished.
+    pub fn stats(&self) -> RenderStats {
+        let count = |counter: Counter| self.counts[counter as usize].load(Ordering::Relaxed);
+        RenderStats {
+            primary_rays: count(Counter::PrimaryRay),
+            secondary_rays: count(Counter::SecondaryRay),
+            shadow_rays: count(Counter::ShadowRay),
+            nodes_visited: count(Counter::NodeVisited),
+            primitive_tests: count(Counter::PrimitiveTest),
+            path_vertices: count(Counter::PathVertex),
+            time: self
+                .time
+                .get()
+                .copied()
+                .unwrap_or_else(|| self.start.elapsed()),
+        }
+    }
+}
+
+/// Counts being recorded on the current thread, which are added to `counters` when this is
+/// dropped, even if the work being recorded panicked.
+struct Recording<'a> {
+    counters: &'a RenderCounters,
+    /// The counts made on this thread before recording started, which are put back afterwards
+    /// in case this is work stolen by a thread in the middle of recording something else.
+    outer: [u64; COUNTERS],
+}
+
+impl Drop for Recording<'_> {
+    fn drop(&mut self) {
+        COUNTS.with(|counts| {
+            for ((total, count), outer) in self.counters.counts.iter().zip(counts).zip(self.outer) {
+                total.fetch_add(count.replace(outer), Ordering::Relaxed);
+            }
+        });
+    }
+}
+
+impl Default for RenderCounters {
+    fn default() -> Self {
+        RenderCounters::new()
+    }
+}
+
+/// The work a render did and how long it took.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RenderStats {
+    pub primary_rays: u64,
+    pub secondary_rays: u64,
+    pub shadow_rays: u64,
+    pub nodes_visited: u64,
+    pub primitive_tests: u64,
+    pub path_vertices: u64,
+    pub time: Duration,
+}
+
+impl RenderStats {
+    /// Returns the number of rays traced through the scene, of every kind.
+    pub fn rays(&self) -> u64 {
+        self.primary_rays + self.secondary_rays + self.shadow_rays
+    }
+
+    /// Returns the average number of BVH nodes each ray visited.
+    pub fn nodes_per_ray(&self) -> f64 {
+        ratio(self.nodes_visited, self.rays())
+    }
+
+    /// Returns the average number of objects each ray was tested against.
+    pub fn primitive_tests_per_ray(&self) -> f64 {
+        ratio(self.primitive_tests, self.rays())
+    }
+
+    /// Returns the average number of surfaces a path from the camera hit.
+    pub fn average_path_length(&self) -> f64 {
+        ratio(self.path_vertices, self.primary_rays)
+    }
+
+    /// Returns the number of rays traced per second.
+    pub fn rays_per_second(&self) -> f64 {
+        let seconds = self.time.as_secs_f64();
+        if seconds > 0. {
+            self.rays() as f64 / seconds
+        } else {
+            0.
+        }
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Primary rays: {}", self.primary_rays)?;
+        writeln!(f, "Secondary rays: {}", self.secondary_rays)?;
+        writeln!(f, "Shadow rays: {}", self.shadow_rays)?;
+        writeln!(f, "BVH nodes per ray: {:.2}", self.nodes_per_ray())?;
+        writeln!(
+            f,
+            "Primitive tests per ray: {:.2}",
+            self.primitive_tests_per_ray()
+        )?;
+        writeln!(f, "Average path length: {:.2}", self.average_path_length())?;
+        write!(
+            f,
+            "Rays per second: {:.2}M ({:.2}s)",
+            self.rays_per_second() / 1e6,
+            self.time.as_secs_f64()
+        )
+    }
+}
+
+/// Returns `a / b`, or zero when nothing has been counted.
+fn ratio(a: u64, b: u64) -> f64 {
+    if b == 0 {
+        0.
+    } else {
+        a as f64 / b as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_go_to_the_render_recording_them() {
+        let (outer, inner) = (RenderCounters::new(), RenderCounters::new());
+        // Counts made outside any recording, like those of a cancelled render, go nowhere
+        Counter::ShadowRay.increment();
+        outer.record(|| {
+            Counter::PrimaryRay.increment();
+            // Like a pixel of another render run on this thread while it waits
+            inner.record(|| Counter::SecondaryRay.increment());
+            Counter::PrimaryRay.increment();
+        });
+
+        let (outer, inner) = (outer.stats(), inner.stats());
+        assert_eq!(
+            (outer.primary_rays, outer.secondary_rays, outer.shadow_rays),
+            (2, 0, 0)
+        );
+        assert_eq!(
+            (inner.primary_rays, inner.secondary_rays, inner.shadow_rays),
+            (0, 1, 0)
+        );
+    }
+}
@@ -0,0 +1,26 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// How much light the shutter lets through over the time it is open, which shapes the trails
+/// left by moving objects.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ShutterCurve {
+    /// Fully open for the whole interval, giving trails of even brightness
+    #[default]
+    Box,
+    /// Opens and closes linearly, peaking halfway, giving trails that fade out at both ends
+    Triangle,
+}
+
+impl ShutterCurve {
+    /// Maps a number in [0, 1) to a fraction of the way through the shutter interval, spread
+    /// in proportion to how open the shutter is.
+    pub fn sample(self, u: f64) -> f64 {
+        match self {
+            ShutterCurve::Box => u,
+            ShutterCurve::Triangle if u < 0.5 => (2. * u).sqrt() / 2.,
+            ShutterCurve::Triangle => 1. - (2. * (1. - u)).sqrt() / 2.,
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    collections::VecDeque,
+    io::{self, BufReader, BufWriter},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use super::protocol::{receive, send, Tile, ToCoordinator, ToWorker};
+use crate::{
+    core::Camera,
+    film::{AovStats, Checkpoint, PixelStats},
+};
+
+/// How long to wait between checks for new workers.
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The tiles of a render and what has come back from the workers so far.
+struct Progress {
+    /// Tiles no worker is rendering, in the order they are handed out
+    pending: VecDeque<Tile>,
+    /// Tiles that have not come back yet, including those being rendered
+    remaining: usize,
+    checkpoint: Checkpoint,
+}
+
+/// A render shared out between the workers connected to it.
+struct Coordinator<'a> {
+    progress: Mutex<Progress>,
+    /// Signalled whenever a tile is finished or handed back.
+    changed: Condvar,
+    scene: u64,
+    camera: String,
+    aovs: bool,
+    /// How long a worker can go without a word before it is given up on
+    timeout: Duration,
+    bar: &'a ProgressBar,
+}
+
+/// Renders an image with `cam` by splitting it into tiles of `tile_size` pixels square and
+/// handing them out to workers that connect to `address`, recording AOVs if `aovs` is set.
+/// Workers must have loaded the same scene, which is checked against its fingerprint `scene`.
+///
+/// Each pixel is seeded the same wherever it is rendered, so the result is exactly the image a
+/// single machine would have rendered. Workers can join at any point, and if one disconnects,
+/// dies or goes `timeout` without being heard from before returning a tile, the tile is given
+/// to another. This returns once every tile is back, waiting for workers for as long as that
+/// takes.
+pub fn coordinate(
+    address: &str,
+    cam: &Camera,
+    scene: u64,
+    aovs: bool,
+    tile_size: u32,
+    timeout: Duration,
+) -> io::Result<Checkpoint> {
+    let (width, height) = (cam.image_width(), cam.image_height());
+    let pending: VecDeque<_> = (0..height)
+        .step_by(tile_size as usize)
+        .flat_map(|y| {
+            (0..width).step_by(tile_size as usize).map(move |x| Tile {
+                x,
+                y,
+                width: tile_size.min(width - x),
+                height: tile_size.min(height - y),
+            })
+        })
+        .collect();
+
+    let bar = ProgressBar::new(pending.len() as u64);
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{elapsed_precise} [{wide_bar}] {pos}/{len} tiles ({eta} remaining)",
+        )
+        .unwrap(),
+    );
+    let coordinator = Coordinator {
+        progress: Mutex::new(Progress {
+            remaining: pending.len(),
+            pending,
+            checkpoint: cam.checkpoint(scene, aovs),
+        }),
+        changed: Condvar::new(),
+        scene,
+        camera: toml::to_string(&cam.to_builder()).map_err(io::Error::other)?,
+        aovs,
+        timeout,
+        bar: &bar,
+    };
+
+    let listener = TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+    let local = listener.local_addr()?;
+    bar.suspend(|| println!("Waiting for workers on {local}"));
+
+    thread::scope(|s| {
+        while coordinator.progress.lock().unwrap().remaining > 0 {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    let coordinator = &coordinator;
+                    s.spawn(move || coordinator.serve(stream, peer));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_INTERVAL),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    })?;
+    bar.finish();
+
+    Ok(coordinator.progress.into_inner().unwrap().checkpoint)
+}
+
+impl Coordinator<'_> {
+    /// Hands tiles to the worker on `stream` until there are none left or it goes away.
+    fn serve(&self, stream: TcpStream, peer: SocketAddr) {
+        let mut tile = None;
+        if let Err(e) = self.serve_tiles(stream, peer, &mut tile) {
+            if let Some(tile) = tile {
+                self.hand_back(tile);
+            }
+            let reason = match e.kind() {
+                io::ErrorKind::UnexpectedEof => ", it disconnected".to_string(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+                    ", it stopped responding".to_string()
+                }
+                _ => format!(": {e}"),
+            };
+            self.bar.suspend(|| eprintln!("Lost worker {peer}{reason}"));
+        }
+    }
+
+    /// Does the work of [`Coordinator::serve`], keeping the tile the worker has in `tile` so it
+    /// can be handed back if anything goes wrong.
+    fn serve_tiles(
+        &self,
+        stream: TcpStream,
+        peer: SocketAddr,
+        tile: &mut Option<Tile>,
+    ) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        // Workers check in while rendering, so a long silence means one has hung or its
+        // network has gone without the connection being closed
+        stream.set_read_timeout(Some(self.timeout))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream.try_clone()?);
+
+        match receive(&mut reader)? {
+            ToCoordinator::Hello { scene } if scene == self.scene => {}
+            ToCoordinator::Hello { .. } => {
+                let reason = "it loaded a different scene from the coordinator".to_string();
+                send(&mut writer, &ToWorker::Reject(reason))?;
+                self.bar.suspend(|| {
+                    eprintln!("Turned away worker {peer}, it loaded a different scene")
+                });
+                return Ok(());
+            }
+            _ => return Err(unexpected()),
+        }
+        send(
+            &mut writer,
+            &ToWorker::Job {
+                camera: self.camera.clone(),
+                aovs: self.aovs,
+            },
+        )?;
+        self.bar.suspend(|| println!("Worker {peer} joined"));
+
+        while let Some(next) = self.next_tile() {
+            *tile = Some(next);
+            send(&mut writer, &ToWorker::Render(next))?;
+            let mut message = receive(&mut reader)?;
+            while let ToCoordinator::Working = message {
+                message = receive(&mut reader)?;
+            }
+            match message {
+                ToCoordinator::Tile {
+                    tile: done,
+                    pixels,
+                    aovs,
+                } if done == next
+                    && pixels.len() == next.pixels()
+                    && aovs
+                        .as_ref()
+                        .map_or(!self.aovs, |aovs| aovs.len() == next.pixels()) =>
+                {
+                    self.finish_tile(next, pixels, aovs);
+                    *tile = None;
+                }
+                _ => return Err(unexpected()),
+            }
+        }
+        send(&mut writer, &ToWorker::Finished)
+    }
+
+    /// Takes a tile to render, waiting while every tile left is with another worker in case
+    /// one of them is handed back. Returns `None` once every tile is done.
+    fn next_tile(&self) -> Option<Tile> {
+        let mut progress = self.progress.lock().unwrap();
+        loop {
+            if let Some(tile) = progress.pending.pop_front() {
+                return Some(tile);
+            }
+            if progress.remaining == 0 {
+                return None;
+            }
+            progress = self.changed.wait(progress).unwrap();
+        }
+    }
+
+    /// Puts a tile a worker didn't finish back at the front of the queue.
+    fn hand_back(&self, tile: Tile) {
+        self.progress.lock().unwrap().pending.push_front(tile);
+        self.changed.notify_all();
+    }
+
+    /// Stores the pixels of a finished tile, which come row by row.
+    fn finish_tile(&self, tile: Tile, pixels: Vec<PixelStats>, aovs: Option<Vec<AovStats>>) {
+        let mut progress = self.progress.lock().unwrap();
+        let width = progress.checkpoint.width() as usize;
+        for (k, stats) in pixels.into_iter().enumerate() {
+            let i = tile.x as usize + k % tile.width as usize;
+            let j = tile.y as usize + k / tile.width as usize;
+            let aov = aovs.as_ref().map_or_else(AovStats::default, |aovs| aovs[k]);
+            progress.checkpoint.set_pixel(i + j * width, stats, aov);
+        }
+        progress.remaining -= 1;
+        self.changed.notify_all();
+        self.bar.inc(1);
+    }
+}
+
+fn unexpected() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "unexpected message from the worker",
+    )
+}
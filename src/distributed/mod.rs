@@ -0,0 +1,6 @@
+mod coordinator;
+mod protocol;
+mod worker;
+
+pub use coordinator::coordinate;
+pub use worker::work;
@@ -0,0 +1,91 @@
+use std::{
+    io::{self, Read, Write},
+    ops::Range,
+    time::Duration,
+};
+
+use bincode::Options;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::film::{AovStats, PixelStats};
+
+/// The largest message either side will read, which keeps a garbled length from allocating
+/// without limit.
+const MESSAGE_LIMIT: u64 = 1 << 30;
+
+/// How often a worker lets the coordinator know it is still rendering a tile.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A rectangle of pixels.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    pub fn columns(&self) -> Range<u32> {
+        self.x..self.x + self.width
+    }
+
+    pub fn rows(&self) -> Range<u32> {
+        self.y..self.y + self.height
+    }
+
+    /// Returns the number of pixels in the tile.
+    pub fn pixels(&self) -> usize {
+        (self.width * self.height) as usize
+    }
+}
+
+/// What a worker sends to the coordinator.
+#[derive(Serialize, Deserialize)]
+pub enum ToCoordinator {
+    /// Sent once on connecting, with a fingerprint of the scene the worker loaded
+    Hello { scene: u64 },
+    /// Sent every [`HEARTBEAT_INTERVAL`] while a tile is being rendered
+    Working,
+    /// The statistics of every pixel of a tile row by row, along with their AOVs if the job
+    /// records them
+    Tile {
+        tile: Tile,
+        pixels: Vec<PixelStats>,
+        aovs: Option<Vec<AovStats>>,
+    },
+}
+
+/// What the coordinator sends to a worker.
+#[derive(Serialize, Deserialize)]
+pub enum ToWorker {
+    /// The camera to render with, as TOML since the camera settings are only written that way,
+    /// and whether to record AOVs
+    Job { camera: String, aovs: bool },
+    /// The worker can't help with this render, for the reason given
+    Reject(String),
+    /// A tile to render
+    Render(Tile),
+    /// Every tile is done
+    Finished,
+}
+
+fn options() -> impl Options {
+    bincode::DefaultOptions::new().with_limit(MESSAGE_LIMIT)
+}
+
+/// Writes `message` to `writer` and flushes it.
+pub fn send<T: Serialize>(writer: &mut impl Write, message: &T) -> io::Result<()> {
+    options()
+        .serialize_into(&mut *writer, message)
+        .map_err(io::Error::other)?;
+    writer.flush()
+}
+
+/// Reads a message sent with [`send`].
+pub fn receive<T: DeserializeOwned>(reader: &mut impl Read) -> io::Result<T> {
+    options().deserialize_from(reader).map_err(|e| match *e {
+        bincode::ErrorKind::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    })
+}
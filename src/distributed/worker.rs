@@ -0,0 +1,109 @@
+use std::{
+    io::{self, BufReader, BufWriter, Write},
+    net::TcpStream,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+use super::protocol::{receive, send, ToCoordinator, ToWorker, HEARTBEAT_INTERVAL};
+use crate::core::{Camera, CameraBuilder, Hittable, RenderCounters, RenderStats};
+
+/// How many times a worker tries to reach a coordinator that is not listening yet, a second
+/// apart, so that both can be started together.
+const CONNECT_ATTEMPTS: u32 = 10;
+
+/// Renders tiles for the coordinator at `address` until it has no more to hand out, returning
+/// the work this worker did. `world` and `cam` are the scene as this worker loaded it, and
+/// `scene` is its fingerprint, which the coordinator checks against its own. The camera
+/// settings come from the coordinator, apart from the environment, which belongs to the scene.
+pub fn work(
+    address: &str,
+    world: &dyn Hittable,
+    cam: &Camera,
+    scene: u64,
+) -> io::Result<RenderStats> {
+    let stream = connect(address)?;
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    send(&mut writer, &ToCoordinator::Hello { scene })?;
+    let (camera, aovs) = match receive(&mut reader)? {
+        ToWorker::Job { camera, aovs } => (camera, aovs),
+        ToWorker::Reject(reason) => {
+            return Err(io::Error::other(format!(
+                "the coordinator refused: {reason}"
+            )))
+        }
+        _ => return Err(unexpected()),
+    };
+    let mut builder: CameraBuilder =
+        toml::from_str(&camera).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if let Some(environment) = cam.environment() {
+        builder = builder.environment(environment.clone());
+    }
+    let cam = builder.build();
+
+    let counters = RenderCounters::new();
+    loop {
+        match receive(&mut reader)? {
+            ToWorker::Render(tile) => {
+                let (pixels, aov_pixels) = with_heartbeat(&mut writer, || {
+                    cam.render_region(world, tile.columns(), tile.rows(), aovs, &counters)
+                        .into_iter()
+                        .unzip()
+                })?;
+                let aovs = aovs.then_some(aov_pixels);
+                send(&mut writer, &ToCoordinator::Tile { tile, pixels, aovs })?;
+            }
+            ToWorker::Finished => break,
+            _ => return Err(unexpected()),
+        }
+    }
+    counters.finish();
+    Ok(counters.stats())
+}
+
+/// Connects to `address`, trying again while nothing is listening there.
+fn connect(address: &str) -> io::Result<TcpStream> {
+    let mut attempts = 1;
+    loop {
+        match TcpStream::connect(address) {
+            Err(e)
+                if e.kind() == io::ErrorKind::ConnectionRefused && attempts < CONNECT_ATTEMPTS =>
+            {
+                thread::sleep(Duration::from_secs(1));
+                attempts += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Runs `render`, telling the coordinator on `writer` every [`HEARTBEAT_INTERVAL`] that this
+/// worker is still busy, so that it doesn't take a slow tile for a lost one.
+fn with_heartbeat<T: Send>(
+    writer: &mut (impl Write + Send),
+    render: impl FnOnce() -> T,
+) -> io::Result<T> {
+    let (done, finished) = mpsc::channel::<()>();
+    thread::scope(|s| {
+        let heartbeat = s.spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(HEARTBEAT_INTERVAL) {
+                send(writer, &ToCoordinator::Working)?;
+            }
+            Ok(())
+        });
+        let result = render();
+        drop(done);
+        heartbeat.join().unwrap().map(|()| result)
+    })
+}
+
+fn unexpected() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "unexpected message from the coordinator",
+    )
+}
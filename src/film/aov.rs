@@ -0,0 +1,307 @@
+use std::{io, path::Path};
+
+use clap::ValueEnum;
+use exr::prelude::{AnyChannel, AnyChannels, FlatSamples, Image, WritableImage};
+use image::{ImageError, ImageResult, Rgb, RgbImage};
+use nalgebra::{vector, Point3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use super::FrameBuffer;
+use crate::utility::linear_to_srgb;
+
+/// An arbitrary output variable: something about what each pixel first sees, rendered
+/// alongside the image for compositing and denoising.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Aov {
+    /// The shading normal in world space, facing the camera
+    Normal,
+    /// The colour of the material, without any lighting
+    Albedo,
+    /// The distance from the camera
+    Depth,
+    /// The point hit in world space
+    Position,
+    /// The texture coordinates of the point hit
+    Uv,
+    /// One more than the index of the object hit in the scene, or zero where nothing is hit
+    ObjectId,
+    /// A number for each distinct material in the scene, or zero where nothing is hit
+    MaterialId,
+}
+
+impl Aov {
+    /// Every AOV, in the order they are written.
+    pub const ALL: [Aov; 7] = [
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::Depth,
+        Aov::Position,
+        Aov::Uv,
+        Aov::ObjectId,
+        Aov::MaterialId,
+    ];
+
+    /// Returns the name used for this AOV in file names and EXR layers.
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::Uv => "uv",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+        }
+    }
+
+    /// Returns the names of the channels this AOV is written with.
+    fn channels(self) -> &'static [&'static str] {
+        match self {
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Albedo => &["R", "G", "B"],
+            Aov::Depth => &["Z"],
+            Aov::Uv => &["U", "V"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+        }
+    }
+
+    /// Returns the value of this AOV in `sample` as three channels. Single values are repeated
+    /// in each channel, and texture coordinates leave the last one at zero.
+    pub fn value(self, sample: &AovSample) -> Vector3<f64> {
+        match self {
+            Aov::Normal => sample.normal,
+            Aov::Albedo => sample.albedo,
+            Aov::Depth => Vector3::repeat(sample.depth),
+            Aov::Position => sample.position.coords,
+            Aov::Uv => vector![sample.uv[0], sample.uv[1], 0.],
+            Aov::ObjectId => Vector3::repeat(sample.object_id as f64),
+            Aov::MaterialId => Vector3::repeat(sample.material_id as f64),
+        }
+    }
+}
+
+/// What a camera ray first hits. Everything is left at zero where it hits nothing.
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+pub struct AovSample {
+    pub normal: Vector3<f64>,
+    pub albedo: Vector3<f64>,
+    pub depth: f64,
+    pub position: Point3<f64>,
+    pub uv: [f64; 2],
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+/// Running AOVs of the samples taken for one pixel. Values are averaged with the size of the
+/// filter weights the colour has, leaving out their sign, as the negative lobes of a filter
+/// would push albedos and depths outside anything the scene holds. IDs cannot be blended so
+/// they come from the first sample.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct AovStats {
+    sum: AovSample,
+    weight_sum: f64,
+    ids: Option<(u32, u32)>,
+}
+
+impl AovStats {
+    /// Adds a sample with the filter weight `weight`.
+    pub fn add(&mut self, sample: &AovSample, weight: f64) {
+        let weight = weight.abs();
+        self.sum.normal += weight * sample.normal;
+        self.sum.albedo += weight * sample.albedo;
+        self.sum.depth += weight * sample.depth;
+        self.sum.position += weight * sample.position.coords;
+        self.sum.uv[0] += weight * sample.uv[0];
+        self.sum.uv[1] += weight * sample.uv[1];
+        self.weight_sum += weight;
+        self.ids
+            .get_or_insert((sample.object_id, sample.material_id));
+    }
+
+    /// Returns the weighted average of the samples, or zeros if their weights add up to zero.
+    pub fn mean(&self) -> AovSample {
+        let (object_id, material_id) = self.ids.unwrap_or_default();
+        if self.weight_sum == 0. {
+            return AovSample {
+                object_id,
+                material_id,
+                ..AovSample::default()
+            };
+        }
+
+        let scale = 1. / self.weight_sum;
+        AovSample {
+            normal: self.sum.normal * scale,
+            albedo: self.sum.albedo * scale,
+            depth: self.sum.depth * scale,
+            position: Point3::from(self.sum.position.coords * scale),
+            uv: [self.sum.uv[0] * scale, self.sum.uv[1] * scale],
+            object_id,
+            material_id,
+        }
+    }
+}
+
+/// The AOVs of every pixel, stored row by row from the top left.
+#[derive(Clone)]
+pub struct AovBuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<AovSample>,
+}
+
+impl AovBuffer {
+    /// Creates an [`AovBuffer`] where nothing has been hit.
+    pub fn new(width: u32, height: u32) -> AovBuffer {
+        AovBuffer::from_pixels(
+            width,
+            height,
+            vec![AovSample::default(); (width * height) as usize],
+        )
+    }
+
+    /// Creates an [`AovBuffer`] from pixels stored row by row from the top left.
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<AovSample>) -> AovBuffer {
+        assert_eq!(pixels.len(), (width * height) as usize);
+        AovBuffer {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the pixels of this [`AovBuffer`] row by row from the top left.
+    pub fn pixels(&self) -> &[AovSample] {
+        &self.pixels
+    }
+
+    /// Returns a mutable reference to the pixels of this [`AovBuffer`].
+    pub fn pixels_mut(&mut self) -> &mut [AovSample] {
+        &mut self.pixels
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> &AovSample {
+        &self.pixels[(x + self.width * y) as usize]
+    }
+
+    /// Returns one AOV as a float image, which can be written on its own with
+    /// [`FrameBuffer::save`].
+    pub fn layer(&self, aov: Aov) -> FrameBuffer {
+        let pixels = self.pixels.iter().map(|p| aov.value(p)).collect();
+        let samples = vec![0; self.pixels.len()];
+        FrameBuffer::from_pixels(self.width, self.height, pixels, samples)
+    }
+
+    /// Draws one AOV so that it can be looked at. Normals are shifted from [-1, 1] into
+    /// [0, 1], depth and position are scaled to the range found in the image, and every ID is
+    /// given its own colour.
+    pub fn preview(&self, aov: Aov) -> RgbImage {
+        let values: Vec<Vector3<f64>> = self.pixels.iter().map(|p| aov.value(p)).collect();
+        let hit = |i: usize| self.pixels[i].depth > 0.;
+        let mut min = Vector3::repeat(f64::MAX);
+        let mut max = Vector3::repeat(f64::MIN);
+        for value in (0..values.len()).filter(|&i| hit(i)).map(|i| &values[i]) {
+            min = min.inf(value);
+            max = max.sup(value);
+        }
+        let range = (max - min).map(|r| if r > 0. { r } else { 1. });
+
+        RgbImage::from_fn(self.width, self.height, |x, y| {
+            let i = (x + self.width * y) as usize;
+            let value = values[i];
+            let display = match aov {
+                Aov::Normal => value.map(|c| c * 0.5 + 0.5),
+                Aov::Albedo | Aov::Uv => value,
+                // Nearer is brighter, and nothing hit is black
+                Aov::Depth if hit(i) => Vector3::repeat(1. - 0.9 * (value.x - min.x) / range.x),
+                Aov::Depth => Vector3::zeros(),
+                Aov::Position => (value - min).component_div(&range),
+                Aov::ObjectId | Aov::MaterialId => return id_colour(value.x as u32),
+            };
+            let encode = |c: f64| (linear_to_srgb(c.clamp(0., 1.)) * 255.).round() as u8;
+            Rgb([encode(display.x), encode(display.y), encode(display.z)])
+        })
+    }
+
+    /// Writes `beauty` and `aovs` to one OpenEXR file. The image is in the `R`, `G` and `B`
+    /// channels, and each AOV is a layer of channels named after it, such as `normal.X` or
+    /// `depth.Z`, as compositing software expects.
+    pub fn save_layers<P: AsRef<Path>>(
+        &self,
+        path: P,
+        beauty: &FrameBuffer,
+        aovs: &[Aov],
+    ) -> ImageResult<()> {
+        assert_eq!((beauty.width(), beauty.height()), (self.width, self.height));
+
+        let channel = |name: String, value: &dyn Fn(usize) -> f64| {
+            let samples = (0..self.pixels.len()).map(|i| value(i) as f32).collect();
+            AnyChannel::new(name.as_str(), FlatSamples::F32(samples))
+        };
+
+        let mut channels = vec![];
+        for (c, name) in ["R", "G", "B"].into_iter().enumerate() {
+            channels.push(channel(name.to_owned(), &|i| beauty.pixels()[i][c]));
+        }
+        for &aov in aovs {
+            for (c, name) in aov.channels().iter().enumerate() {
+                let name = format!("{}.{name}", aov.name());
+                channels.push(channel(name, &|i| aov.value(&self.pixels[i])[c]));
+            }
+        }
+
+        let size = (self.width as usize, self.height as usize);
+        Image::from_channels(size, AnyChannels::sort(channels.into()))
+            .write()
+            .to_file(path)
+            .map_err(|e| ImageError::IoError(io::Error::other(e)))
+    }
+}
+
+/// Picks a bright, well spread colour for an ID, with black for zero.
+fn id_colour(id: u32) -> Rgb<u8> {
+    if id == 0 {
+        return Rgb([0, 0, 0]);
+    }
+    // Scramble the bits so that neighbouring IDs get unrelated colours
+    let mut h = id.wrapping_mul(0x9e37_79b9);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    let [r, g, b, _] = h.to_le_bytes();
+    Rgb([r | 0x40, g | 0x40, b | 0x40])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negative_filter_weights_keep_aovs_in_range() {
+        let sample = |albedo, depth| AovSample {
+            albedo: Vector3::repeat(albedo),
+            depth,
+            object_id: 1,
+            ..AovSample::default()
+        };
+        // A sample in the negative lobe of a filter like Mitchell, next to two positive ones
+        let mut stats = AovStats::default();
+        stats.add(&sample(0.2, 4.), 1.);
+        stats.add(&sample(0.8, 2.), -0.3);
+        stats.add(&sample(0.2, 4.), 1.);
+
+        let mean = stats.mean();
+        assert!((0.2..=0.8).contains(&mean.albedo.x));
+        assert!((2. ..=4.).contains(&mean.depth));
+        assert_eq!(mean.object_id, 1);
+    }
+}
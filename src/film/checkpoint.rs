@@ -0,0 +1,317 @@
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{AovBuffer, AovStats, FrameBuffer, PixelStats};
+
+/// The first bytes of every checkpoint file, ending in the version of the format.
+const MAGIC: &[u8; 8] = b"RTCKPT\x00\x02";
+
+/// An error from reading a checkpoint or carrying on a render from one.
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    /// The file is not a checkpoint, or was written by a different version of the renderer.
+    NotACheckpoint,
+    /// The file starts like a checkpoint but the rest of it could not be read.
+    Corrupt(bincode::Error),
+    /// The image is a different size from the one the checkpoint was made for.
+    Size {
+        checkpoint: (u32, u32),
+        render: (u32, u32),
+    },
+    /// The scene has changed since the checkpoint was made.
+    Scene,
+    /// Camera settings other than the number of samples or the tone mapping have changed since
+    /// the checkpoint was made.
+    Camera,
+    /// AOVs are wanted but the checkpoint was made without them.
+    NoAovs,
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(error) => write!(f, "{error}"),
+            CheckpointError::NotACheckpoint => {
+                write!(f, "not a checkpoint from this version of the renderer")
+            }
+            CheckpointError::Corrupt(error) => write!(f, "checkpoint is corrupt: {error}"),
+            CheckpointError::Size { checkpoint, render } => write!(
+                f,
+                "checkpoint is of a {}x{} image but the render is {}x{}",
+                checkpoint.0, checkpoint.1, render.0, render.1
+            ),
+            CheckpointError::Scene => {
+                write!(f, "the scene has changed since the checkpoint was made")
+            }
+            CheckpointError::Camera => write!(
+                f,
+                "the camera has changed since the checkpoint was made, only the samples per \
+                 pixel, adaptive sampling and tone mapping may differ"
+            ),
+            CheckpointError::NoAovs => write!(f, "checkpoint was made without AOVs"),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(error: io::Error) -> Self {
+        CheckpointError::Io(error)
+    }
+}
+
+/// A render part of the way through, which can be written to disk and carried on later. It
+/// holds the running statistics of every pixel. Every sample's random numbers are chosen from
+/// the seed, the pixel and the number of samples the pixel already has, so along with the
+/// sequence the sampler was set up for, that is all that is needed to take the next samples
+/// exactly as an uninterrupted render would have.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    width: u32,
+    height: u32,
+    /// Fingerprints of the scene and of the camera settings the samples were taken with.
+    scene: u64,
+    camera: u64,
+    /// The samples per pixel the sampler was first set up for. Stratified sampling lays out
+    /// its strata for this many samples, so a render carried on to more samples keeps these
+    /// strata and moves on to further rounds of them.
+    sequence_samples: u32,
+    pixels: Vec<PixelStats>,
+    aovs: Option<Vec<AovStats>>,
+}
+
+impl Checkpoint {
+    /// Creates a [`Checkpoint`] for a render that has not taken any samples yet, recording AOVs
+    /// if `aovs` is set.
+    pub fn new(
+        width: u32,
+        height: u32,
+        scene: u64,
+        camera: u64,
+        sequence_samples: u32,
+        aovs: bool,
+    ) -> Checkpoint {
+        let pixels = (width * height) as usize;
+        Checkpoint {
+            width,
+            height,
+            scene,
+            camera,
+            sequence_samples,
+            pixels: vec![PixelStats::default(); pixels],
+            aovs: aovs.then(|| vec![AovStats::default(); pixels]),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn sequence_samples(&self) -> u32 {
+        self.sequence_samples
+    }
+
+    /// Returns whether AOVs are being recorded.
+    pub fn has_aovs(&self) -> bool {
+        self.aovs.is_some()
+    }
+
+    /// Returns the statistics of pixel number `n`, counting row by row from the top left.
+    pub fn pixel(&self, n: usize) -> (PixelStats, AovStats) {
+        let aovs = self
+            .aovs
+            .as_ref()
+            .map_or_else(AovStats::default, |aovs| aovs[n]);
+        (self.pixels[n], aovs)
+    }
+
+    /// Replaces the statistics of pixel number `n`.
+    pub fn set_pixel(&mut self, n: usize, stats: PixelStats, aovs: AovStats) {
+        self.pixels[n] = stats;
+        if let Some(all) = &mut self.aovs {
+            all[n] = aovs;
+        }
+    }
+
+    /// Checks that a render of a `width` by `height` image, of the scene and with the camera
+    /// settings with the fingerprints given, can carry on from this checkpoint. If it needs
+    /// AOVs, so must the checkpoint.
+    pub fn check(
+        &self,
+        (width, height): (u32, u32),
+        scene: u64,
+        camera: u64,
+        aovs: bool,
+    ) -> Result<(), CheckpointError> {
+        if (width, height) != (self.width, self.height) {
+            Err(CheckpointError::Size {
+                checkpoint: (self.width, self.height),
+                render: (width, height),
+            })
+        } else if scene != self.scene {
+            Err(CheckpointError::Scene)
+        } else if camera != self.camera {
+            Err(CheckpointError::Camera)
+        } else if aovs && !self.has_aovs() {
+            Err(CheckpointError::NoAovs)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the average colour of every pixel so far.
+    pub fn frame(&self) -> FrameBuffer {
+        let pixels = self.pixels.iter().map(PixelStats::mean).collect();
+        let samples = self.pixels.iter().map(PixelStats::count).collect();
+        FrameBuffer::from_pixels(self.width, self.height, pixels, samples)
+    }
+
+    /// Returns the average AOVs of every pixel so far, if they are being recorded.
+    pub fn aov_buffer(&self) -> Option<AovBuffer> {
+        let aovs = self.aovs.as_ref()?;
+        let pixels = aovs.iter().map(AovStats::mean).collect();
+        Some(AovBuffer::from_pixels(self.width, self.height, pixels))
+    }
+
+    /// Reads a checkpoint written by [`Checkpoint::save`].
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Checkpoint, CheckpointError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0; MAGIC.len()];
+        match reader.read_exact(&mut magic) {
+            Ok(()) if &magic == MAGIC => {}
+            Ok(()) => return Err(CheckpointError::NotACheckpoint),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(CheckpointError::NotACheckpoint)
+            }
+            Err(e) => return Err(e.into()),
+        }
+        bincode::deserialize_from(reader).map_err(CheckpointError::Corrupt)
+    }
+
+    /// Writes the checkpoint to `path`. It is written to a temporary file next to `path` first
+    /// and then moved into place, so if the process dies while writing, the last checkpoint is
+    /// still there.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        writer.write_all(MAGIC)?;
+        bincode::serialize_into(&mut writer, self).map_err(io::Error::other)?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        fs::rename(&temporary, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use super::*;
+    use crate::{core::RenderCounters, scenes::by_name, shapes::BvhNode};
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("raytracer-{}-{name}.ckpt", std::process::id()))
+    }
+
+    #[test]
+    fn resuming_to_more_samples_matches_a_straight_render() {
+        let (world, cam) = by_name("cornel_box").unwrap()(0);
+        let nodes = BvhNode::new(&world.objects);
+        let settings = cam.to_builder().image_width(16).seed(5);
+        let (short, long) = (
+            settings.clone().samples_per_pixel(2).build(),
+            settings.samples_per_pixel(6).build(),
+        );
+
+        let counters = RenderCounters::new();
+        let mut checkpoint = short.checkpoint(1, true);
+        short.render_from(&nodes, &mut checkpoint, &counters, Duration::MAX, &|_| {});
+        let path = path("resume");
+        checkpoint.save(&path).unwrap();
+
+        let mut resumed = Checkpoint::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let size = (long.image_width(), long.image_height());
+        resumed.check(size, 1, long.fingerprint(), true).unwrap();
+        long.render_from(&nodes, &mut resumed, &counters, Duration::MAX, &|_| {});
+
+        let (frame, aovs) = long.render_film(&nodes, true, &counters);
+        assert!(resumed.frame().pixels() == frame.pixels());
+        assert!(resumed.frame().sample_counts() == frame.sample_counts());
+        let depths = |aovs: AovBuffer| aovs.pixels().iter().map(|a| a.depth).collect::<Vec<_>>();
+        assert!(depths(resumed.aov_buffer().unwrap()) == depths(aovs.unwrap()));
+    }
+
+    #[test]
+    fn opening_reports_what_is_wrong_with_the_file() {
+        let missing = Checkpoint::open(path("missing"));
+        assert!(matches!(missing, Err(CheckpointError::Io(_))));
+
+        for (name, contents) in [("other", &b"P3 4 4 255"[..]), ("short", b"RTC")] {
+            let path = path(name);
+            fs::write(&path, contents).unwrap();
+            let opened = Checkpoint::open(&path);
+            fs::remove_file(&path).unwrap();
+            assert!(
+                matches!(opened, Err(CheckpointError::NotACheckpoint)),
+                "{name}"
+            );
+        }
+
+        // A checkpoint cut off partway through
+        let path = path("truncated");
+        Checkpoint::new(4, 4, 1, 2, 8, false).save(&path).unwrap();
+        let length = fs::metadata(&path).unwrap().len();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(length / 2)
+            .unwrap();
+        let opened = Checkpoint::open(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(opened, Err(CheckpointError::Corrupt(_))));
+    }
+
+    #[test]
+    fn checking_reports_what_has_changed() {
+        let checkpoint = Checkpoint::new(4, 3, 1, 2, 8, false);
+        assert!(checkpoint.check((4, 3), 1, 2, false).is_ok());
+        assert!(matches!(
+            checkpoint.check((3, 4), 1, 2, false),
+            Err(CheckpointError::Size {
+                checkpoint: (4, 3),
+                render: (3, 4)
+            })
+        ));
+        assert!(matches!(
+            checkpoint.check((4, 3), 9, 2, false),
+            Err(CheckpointError::Scene)
+        ));
+        assert!(matches!(
+            checkpoint.check((4, 3), 1, 9, false),
+            Err(CheckpointError::Camera)
+        ));
+        assert!(matches!(
+            checkpoint.check((4, 3), 1, 2, true),
+            Err(CheckpointError::NoAovs)
+        ));
+    }
+}
@@ -0,0 +1,229 @@
+use nalgebra::Vector3;
+use rayon::prelude::*;
+
+use super::{AovBuffer, FrameBuffer};
+
+/// The weights of the B3 spline the filter blurs with, spread further apart each pass.
+const KERNEL: [f64; 5] = [1. / 16., 1. / 4., 3. / 8., 1. / 4., 1. / 16.];
+/// The number of passes, which reach out to 2 + 4 + 8 + 16 + 32 pixels in each direction.
+const PASSES: u32 = 5;
+
+/// How much difference in each feature is tolerated before neighbours stop being blended in.
+/// Colour is compared after compressing it into [0, 1), and is held tighter each pass so that
+/// edges the first passes keep are not blurred by the wider later ones.
+const SIGMA_COLOUR: f64 = 0.6;
+const SIGMA_NORMAL: f64 = 0.3;
+const SIGMA_ALBEDO: f64 = 0.1;
+/// Depth is compared relative to the depth of the pixel being filtered.
+const SIGMA_DEPTH: f64 = 0.05;
+
+/// Removes noise from `frame` with an edge-avoiding à-trous wavelet filter, as described by
+/// Dammertz et al. Each pixel is averaged with neighbours that have a similar colour, normal,
+/// albedo and depth in `aovs`, so edges stay sharp while flat areas are smoothed. The lighting
+/// is filtered apart from the albedo, which keeps texture detail.
+pub fn denoise(frame: &FrameBuffer, aovs: &AovBuffer) -> FrameBuffer {
+    assert_eq!(
+        (frame.width(), frame.height()),
+        (aovs.width(), aovs.height())
+    );
+
+    // Divide out the albedo so that only the lighting is blurred
+    let albedo = |i: usize| {
+        aovs.pixels()[i]
+            .albedo
+            .map(|c| if c > 1e-3 { c } else { 1. })
+    };
+    let lighting: Vec<Vector3<f64>> = (0..frame.pixels().len())
+        .map(|i| frame.pixels()[i].component_div(&albedo(i)))
+        .collect();
+
+    let mut lighting = clamp_fireflies(&lighting, aovs);
+    for pass in 0..PASSES {
+        lighting = filter_pass(
+            &lighting,
+            aovs,
+            1 << pass,
+            SIGMA_COLOUR / (1 << pass) as f64,
+        );
+    }
+
+    let pixels = lighting
+        .iter()
+        .enumerate()
+        .map(|(i, l)| l.component_mul(&albedo(i)))
+        .collect();
+    FrameBuffer::from_pixels(
+        frame.width(),
+        frame.height(),
+        pixels,
+        frame.sample_counts().to_vec(),
+    )
+}
+
+/// Limits each pixel to the brightest of its eight neighbours. A lone bright pixel is too far
+/// from everything around it in colour to be blended away, so it would otherwise be left as a
+/// speck in the smooth result.
+fn clamp_fireflies(lighting: &[Vector3<f64>], aovs: &AovBuffer) -> Vec<Vector3<f64>> {
+    let (width, height) = (aovs.width() as i64, aovs.height() as i64);
+
+    (0..width * height)
+        .into_par_iter()
+        .map(|p| {
+            let (x, y) = (p % width, p / width);
+            let mut max = Vector3::repeat(f64::MIN);
+            for qy in (y - 1).max(0)..(y + 2).min(height) {
+                for qx in (x - 1).max(0)..(x + 2).min(width) {
+                    if (qx, qy) != (x, y) {
+                        max = max.sup(&lighting[(qx + qy * width) as usize]);
+                    }
+                }
+            }
+            lighting[p as usize].inf(&max)
+        })
+        .collect()
+}
+
+/// Blurs `lighting` once with the kernel's taps `step` pixels apart.
+fn filter_pass(
+    lighting: &[Vector3<f64>],
+    aovs: &AovBuffer,
+    step: i64,
+    sigma_colour: f64,
+) -> Vec<Vector3<f64>> {
+    let (width, height) = (aovs.width() as i64, aovs.height() as i64);
+    let compress = |c: &Vector3<f64>| c.map(|c| c.max(0.) / (1. + c.max(0.)));
+
+    (0..width * height)
+        .into_par_iter()
+        .map(|p| {
+            let (x, y) = (p % width, p / width);
+            let centre = &aovs.pixels()[p as usize];
+            let colour = compress(&lighting[p as usize]);
+
+            let mut sum = Vector3::zeros();
+            let mut weight_sum = 0.;
+            for (j, ky) in KERNEL.iter().enumerate() {
+                for (i, kx) in KERNEL.iter().enumerate() {
+                    let qx = x + (i as i64 - 2) * step;
+                    let qy = y + (j as i64 - 2) * step;
+                    if !(0..width).contains(&qx) || !(0..height).contains(&qy) {
+                        continue;
+                    }
+                    let q = (qx + qy * width) as usize;
+                    let other = &aovs.pixels()[q];
+
+                    // Pixels that see nothing are only blended with each other
+                    if (centre.depth > 0.) != (other.depth > 0.) {
+                        continue;
+                    }
+                    let depth = if centre.depth > 0. {
+                        (centre.depth - other.depth) / centre.depth
+                    } else {
+                        0.
+                    };
+                    let distance = (colour - compress(&lighting[q])).norm_squared()
+                        / (sigma_colour * sigma_colour)
+                        + (centre.normal - other.normal).norm_squared()
+                            / (SIGMA_NORMAL * SIGMA_NORMAL)
+                        + (centre.albedo - other.albedo).norm_squared()
+                            / (SIGMA_ALBEDO * SIGMA_ALBEDO)
+                        + depth * depth / (SIGMA_DEPTH * SIGMA_DEPTH);
+
+                    let weight = kx * ky * (-distance).exp();
+                    sum += weight * lighting[q];
+                    weight_sum += weight;
+                }
+            }
+            // The centre tap always has a weight, so this is never zero
+            sum / weight_sum
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::vector;
+
+    use super::*;
+    use crate::film::AovSample;
+
+    const SIZE: u32 = 32;
+
+    /// Noise that looks random but is the same every run, averaging zero.
+    fn noise(i: usize) -> f64 {
+        ((i * 7919 + 13) % 101) as f64 / 100. - 0.5
+    }
+
+    /// Two walls meeting down the middle of the image, the left dim and facing the camera, the
+    /// right bright and facing sideways, with `albedo` giving the colour of each pixel.
+    fn walls(albedo: impl Fn(usize) -> f64) -> (FrameBuffer, AovBuffer) {
+        let (mut frame, mut aovs) = (FrameBuffer::new(SIZE, SIZE), AovBuffer::new(SIZE, SIZE));
+        for i in 0..(SIZE * SIZE) as usize {
+            let left = (i as u32 % SIZE) < SIZE / 2;
+            let lighting = if left { 0.2 } else { 1. };
+            frame.pixels_mut()[i] = Vector3::repeat(albedo(i) * lighting * (1. + noise(i)));
+            aovs.pixels_mut()[i] = AovSample {
+                normal: if left {
+                    vector![0., 0., 1.]
+                } else {
+                    vector![1., 0., 0.]
+                },
+                albedo: Vector3::repeat(albedo(i)),
+                depth: 2.,
+                ..AovSample::default()
+            };
+        }
+        (frame, aovs)
+    }
+
+    /// Returns the root mean square difference from `expected` of the pixels in `columns`.
+    fn error(frame: &FrameBuffer, columns: std::ops::Range<u32>, expected: f64) -> f64 {
+        let mut sum = 0.;
+        for y in 0..SIZE {
+            for x in columns.clone() {
+                sum += (frame.pixels()[(x + y * SIZE) as usize].x - expected).powi(2);
+            }
+        }
+        (sum / (SIZE * columns.len() as u32) as f64).sqrt()
+    }
+
+    #[test]
+    fn smooths_noise_but_keeps_edges() {
+        let (frame, aovs) = walls(|_| 0.5);
+        let denoised = denoise(&frame, &aovs);
+
+        let half = SIZE / 2;
+        for (columns, expected) in [(0..half, 0.1), (half..SIZE, 0.5)] {
+            let before = error(&frame, columns.clone(), expected);
+            let after = error(&denoised, columns.clone(), expected);
+            assert!(
+                after < before / 3.,
+                "{after} against {before} in {columns:?}"
+            );
+        }
+        // The columns either side of the edge stay apart rather than meeting in the middle
+        let edge = |x| error(&denoised, x..x + 1, 0.);
+        assert!(edge(half - 1) < 0.12 && edge(half) > 0.45);
+    }
+
+    #[test]
+    fn keeps_texture_detail() {
+        // A checkerboard albedo under the same lighting is all detail and no noise
+        let checker = |i: usize| {
+            let (x, y) = (i as u32 % SIZE, i as u32 / SIZE);
+            if (x + y) % 2 == 0 {
+                0.9
+            } else {
+                0.1
+            }
+        };
+        let (mut frame, aovs) = walls(checker);
+        for (i, pixel) in frame.pixels_mut().iter_mut().enumerate() {
+            *pixel /= 1. + noise(i);
+        }
+        let denoised = denoise(&frame, &aovs);
+        for (before, after) in frame.pixels().iter().zip(denoised.pixels()) {
+            assert!((before - after).norm() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::f64::consts::PI;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// A pixel reconstruction filter, which weights each sample by where it lands relative to the
+/// centre of its pixel. Samples are taken over a square reaching `radius` pixels either side of
+/// the centre, and the pixel is the weighted average of them. The filters are separable, so the
+/// weight is the product of the filter across and down.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    /// Every sample counts equally
+    #[default]
+    Box,
+    /// Weights fall off linearly to zero at the radius
+    Tent,
+    /// A Gaussian with a standard deviation of a third of the radius
+    Gaussian,
+    /// The Mitchell-Netravali cubic with B = C = 1/3, which is sharper than a Gaussian
+    Mitchell,
+    /// A windowed sinc with as many lobes as the radius, the sharpest of all but prone to ringing
+    Lanczos,
+}
+
+impl Filter {
+    /// Returns the radius in pixels this filter is usually used with.
+    pub fn default_radius(self) -> f64 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Tent => 1.,
+            Filter::Gaussian => 1.5,
+            Filter::Mitchell => 2.,
+            Filter::Lanczos => 2.,
+        }
+    }
+
+    /// Returns the weight of a sample `x` pixels from the centre of its pixel along one axis.
+    /// Mitchell and Lanczos have negative lobes, so this can be negative.
+    fn evaluate(self, x: f64, radius: f64) -> f64 {
+        let x = x.abs();
+        if x > radius {
+            return 0.;
+        }
+
+        match self {
+            Filter::Box => 1.,
+            Filter::Tent => radius - x,
+            Filter::Gaussian => {
+                let sigma = radius / 3.;
+                let gaussian = |x: f64| (-x * x / (2. * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.)
+            }
+            Filter::Mitchell => mitchell(2. * x / radius),
+            Filter::Lanczos => sinc(x) * sinc(x / radius),
+        }
+    }
+}
+
+/// The number of steps each half of a [`FilterSampler`] table has.
+const TABLE_SIZE: usize = 64;
+
+/// Places samples around the centre of a pixel in proportion to the size of a [`Filter`], so
+/// few samples are wasted where the filter is close to zero. Since the density follows the
+/// filter, a sample's weight is only the filter's sign, scaled to make up for the negative
+/// lobes. The pixel is then the plain average of its weighted samples, which stays well behaved
+/// however few there are, where dividing by a sum of signed weights would not.
+#[derive(Clone)]
+pub struct FilterSampler {
+    filter: Filter,
+    radius: f64,
+    /// The size of the filter over its integral along one axis, which is 1 unless the filter
+    /// has negative lobes.
+    scale: f64,
+    /// The cumulative distribution over the steps of a table spanning `-radius` to `radius`.
+    cdf: Vec<f64>,
+    /// The density of each step of the table.
+    pdf: Vec<f64>,
+}
+
+impl FilterSampler {
+    pub fn new(filter: Filter, radius: f64) -> FilterSampler {
+        let steps = 2 * TABLE_SIZE;
+        let step = 2. * radius / steps as f64;
+        let signed: Vec<f64> = (0..steps)
+            .map(|i| filter.evaluate(-radius + (i as f64 + 0.5) * step, radius))
+            .collect();
+        let values: Vec<f64> = signed.iter().map(|value| value.abs()).collect();
+        let mut sums = vec![0.];
+        for value in &values {
+            sums.push(sums.last().unwrap() + value);
+        }
+        // Dividing by the last sum makes the table end at exactly 1, so no random number in
+        // [0, 1) can land past the last step the filter is non-zero over
+        let total = *sums.last().unwrap();
+        let cdf = sums.iter().map(|sum| sum / total).collect();
+        let pdf = values.iter().map(|value| value / (total * step)).collect();
+
+        FilterSampler {
+            filter,
+            radius,
+            scale: total / signed.iter().sum::<f64>(),
+            cdf,
+            pdf,
+        }
+    }
+
+    /// Maps a point in the unit square to an offset in pixels from the centre of a pixel,
+    /// returning it with its weight.
+    pub fn sample(&self, [u1, u2]: [f64; 2]) -> ([f64; 2], f64) {
+        let (x, wx) = self.sample_1d(u1);
+        let (y, wy) = self.sample_1d(u2);
+        ([x, y], wx * wy)
+    }
+
+    fn sample_1d(&self, u: f64) -> (f64, f64) {
+        // The last step whose start is at or below `u`, skipping steps the filter is zero over
+        let i = (self.cdf.partition_point(|&c| c <= u) - 1).min(self.pdf.len() - 1);
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let t = if width > 0. { (u - self.cdf[i]) / width } else { 0.5 };
+
+        let step = 2. * self.radius / self.pdf.len() as f64;
+        let x = -self.radius + (i as f64 + t) * step;
+        let value = self.filter.evaluate(x, self.radius);
+        let sign = if value < 0. { -1. } else { 1. };
+        (x, sign * self.scale)
+    }
+}
+
+/// The Mitchell-Netravali cubic with B = C = 1/3, which reaches zero at `x` = 2.
+fn mitchell(x: f64) -> f64 {
+    const B: f64 = 1. / 3.;
+    const C: f64 = 1. / 3.;
+    let x2 = x * x;
+    let x3 = x2 * x;
+    if x < 1. {
+        ((12. - 9. * B - 6. * C) * x3 + (-18. + 12. * B + 6. * C) * x2 + (6. - 2. * B)) / 6.
+    } else if x < 2. {
+        ((-B - 6. * C) * x3 + (6. * B + 30. * C) * x2 + (-12. * B - 48. * C) * x
+            + (8. * B + 24. * C))
+            / 6.
+    } else {
+        0.
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        1.
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [Filter; 5] = [
+        Filter::Box,
+        Filter::Tent,
+        Filter::Gaussian,
+        Filter::Mitchell,
+        Filter::Lanczos,
+    ];
+
+    /// Samples one axis of `filter` at evenly spaced points in [0, 1).
+    fn samples(filter: Filter) -> Vec<(f64, f64)> {
+        let sampler = FilterSampler::new(filter, filter.default_radius());
+        let n = 10_000;
+        (0..n)
+            .map(|k| sampler.sample_1d((k as f64 + 0.5) / n as f64))
+            .collect()
+    }
+
+    #[test]
+    fn weights_average_to_one() {
+        // So an image of one colour comes out that colour whatever the filter
+        for filter in FILTERS {
+            let samples = samples(filter);
+            let mean = samples.iter().map(|(_, w)| w).sum::<f64>() / samples.len() as f64;
+            assert!((mean - 1.).abs() < 1e-3, "{filter:?} averages {mean}");
+        }
+    }
+
+    #[test]
+    fn samples_stay_within_the_radius() {
+        for filter in FILTERS {
+            let radius = filter.default_radius();
+            for (x, _) in samples(filter) {
+                assert!(x.abs() <= radius, "{filter:?} sampled {x}");
+            }
+        }
+    }
+
+    #[test]
+    fn only_negative_lobes_give_negative_weights() {
+        for filter in FILTERS {
+            let radius = filter.default_radius();
+            let samples = samples(filter);
+            for &(x, w) in &samples {
+                assert_eq!(w < 0., filter.evaluate(x, radius) < 0., "{filter:?} at {x}");
+            }
+            let negative = samples.iter().any(|&(_, w)| w < 0.);
+            let has_lobes = matches!(filter, Filter::Mitchell | Filter::Lanczos);
+            assert_eq!(negative, has_lobes, "{filter:?}");
+        }
+    }
+}
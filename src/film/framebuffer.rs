@@ -0,0 +1,244 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use image::{codecs::hdr::HdrEncoder, ImageError, ImageResult, Rgb, Rgb32FImage, RgbImage};
+use nalgebra::Vector3;
+
+/// The linear radiance reaching each pixel, before any clamping or gamma, along with the number
+/// of samples taken for it. Pixels are stored row by row from the top left.
+#[derive(Clone)]
+pub struct FrameBuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vector3<f64>>,
+    samples: Vec<u32>,
+}
+
+impl FrameBuffer {
+    /// Creates a black [`FrameBuffer`].
+    pub fn new(width: u32, height: u32) -> FrameBuffer {
+        FrameBuffer {
+            width,
+            height,
+            pixels: vec![Vector3::zeros(); (width * height) as usize],
+            samples: vec![0; (width * height) as usize],
+        }
+    }
+
+    /// Creates a [`FrameBuffer`] from pixels and their sample counts, stored row by row from the
+    /// top left.
+    pub fn from_pixels(
+        width: u32,
+        height: u32,
+        pixels: Vec<Vector3<f64>>,
+        samples: Vec<u32>,
+    ) -> FrameBuffer {
+        assert_eq!(pixels.len(), (width * height) as usize);
+        assert_eq!(samples.len(), (width * height) as usize);
+        FrameBuffer {
+            width,
+            height,
+            pixels,
+            samples,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the pixels of this [`FrameBuffer`] row by row from the top left.
+    pub fn pixels(&self) -> &[Vector3<f64>] {
+        &self.pixels
+    }
+
+    /// Returns a mutable reference to the pixels of this [`FrameBuffer`].
+    pub fn pixels_mut(&mut self) -> &mut [Vector3<f64>] {
+        &mut self.pixels
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Vector3<f64> {
+        self.pixels[(x + self.width * y) as usize]
+    }
+
+    /// Returns the root mean square difference between the channels of this frame and `other`,
+    /// or `None` if they are different sizes. Used to measure how far a render is from a
+    /// converged reference.
+    pub fn rmse(&self, other: &FrameBuffer) -> Option<f64> {
+        if (self.width, self.height) != (other.width, other.height) {
+            return None;
+        }
+        let sum: f64 = self
+            .pixels
+            .iter()
+            .zip(&other.pixels)
+            .map(|(a, b)| (a - b).norm_squared())
+            .sum();
+        Some((sum / (3 * self.pixels.len()) as f64).sqrt())
+    }
+
+    /// Returns the number of samples taken for pixel (`x`, `y`).
+    pub fn sample_count(&self, x: u32, y: u32) -> u32 {
+        self.samples[(x + self.width * y) as usize]
+    }
+
+    /// Returns the sample counts row by row from the top left.
+    pub fn sample_counts(&self) -> &[u32] {
+        &self.samples
+    }
+
+    /// Draws the number of samples taken for each pixel, from dark blue for the fewest through
+    /// to red for the most. Useful for seeing where adaptive sampling spent its time.
+    pub fn sample_heatmap(&self) -> RgbImage {
+        let min = self.samples.iter().copied().min().unwrap_or(0);
+        let max = self.samples.iter().copied().max().unwrap_or(0);
+        let range = (max - min).max(1) as f64;
+
+        RgbImage::from_fn(self.width, self.height, |x, y| {
+            heat_colour((self.sample_count(x, y) - min) as f64 / range)
+        })
+    }
+
+    /// Converts to a 32-bit float image.
+    pub fn to_rgb32f(&self) -> Rgb32FImage {
+        Rgb32FImage::from_fn(self.width, self.height, |x, y| {
+            let p = self.get(x, y);
+            Rgb([p.x as f32, p.y as f32, p.z as f32])
+        })
+    }
+
+    /// Returns true if `path` names one of the floating point formats [`FrameBuffer::save`]
+    /// writes: OpenEXR (`.exr`), Radiance HDR (`.hdr`) or PFM (`.pfm`).
+    pub fn is_float_format<P: AsRef<Path>>(path: P) -> bool {
+        matches!(extension(path.as_ref()).as_deref(), Some("exr" | "hdr" | "pfm"))
+    }
+
+    /// Writes the unclamped radiance to `path` as 32-bit floats. The format is chosen from the
+    /// extension, see [`FrameBuffer::is_float_format`].
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> ImageResult<()> {
+        let path = path.as_ref();
+        match extension(path).as_deref() {
+            Some("exr") => self.to_rgb32f().save(path),
+            Some("hdr") => self.save_hdr(path),
+            Some("pfm") => self.save_pfm(path),
+            _ => self.to_rgb32f().save(path),
+        }
+    }
+
+    /// Reads an image written by [`FrameBuffer::save`]. The sample counts are not stored, so
+    /// they are all zero.
+    pub fn open<P: AsRef<Path>>(path: P) -> ImageResult<FrameBuffer> {
+        let path = path.as_ref();
+        let image = match extension(path).as_deref() {
+            Some("pfm") => open_pfm(path)?,
+            _ => image::open(path)?.into_rgb32f(),
+        };
+        let pixels = image
+            .pixels()
+            .map(|p| Vector3::new(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+        let samples = vec![0; (image.width() * image.height()) as usize];
+        Ok(FrameBuffer::from_pixels(image.width(), image.height(), pixels, samples))
+    }
+
+    fn save_hdr(&self, path: &Path) -> ImageResult<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let image = self.to_rgb32f();
+        let pixels: Vec<Rgb<f32>> = image.pixels().copied().collect();
+        HdrEncoder::new(file).encode(&pixels, self.width as usize, self.height as usize)
+    }
+
+    /// Writes a Portable Float Map, which stores rows from the bottom up.
+    fn save_pfm(&self, path: &Path) -> ImageResult<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        // A negative scale marks the data as little endian
+        write!(file, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let p = self.get(x, y);
+                for c in [p.x, p.y, p.z] {
+                    file.write_all(&(c as f32).to_le_bytes())?;
+                }
+            }
+        }
+        file.flush().map_err(ImageError::IoError)
+    }
+}
+
+/// Reads a colour Portable Float Map in either byte order.
+fn open_pfm(path: &Path) -> ImageResult<Rgb32FImage> {
+    let invalid = || ImageError::IoError(io::Error::new(io::ErrorKind::InvalidData, "invalid PFM"));
+
+    let data = fs::read(path)?;
+    // The header is three whitespace separated fields after the magic number
+    let mut fields = vec![];
+    let mut start = 0;
+    let mut end = 0;
+    while fields.len() < 4 {
+        while data.get(start).ok_or_else(invalid)?.is_ascii_whitespace() {
+            start += 1;
+        }
+        end = start;
+        while !data.get(end).ok_or_else(invalid)?.is_ascii_whitespace() {
+            end += 1;
+        }
+        fields.push(std::str::from_utf8(&data[start..end]).map_err(|_| invalid())?);
+        start = end;
+    }
+    let [magic, width, height, scale] = fields[..] else {
+        return Err(invalid());
+    };
+    let width: u32 = width.parse().map_err(|_| invalid())?;
+    let height: u32 = height.parse().map_err(|_| invalid())?;
+    let scale: f32 = scale.parse().map_err(|_| invalid())?;
+    if magic != "PF" {
+        return Err(invalid());
+    }
+
+    // Exactly one whitespace character separates the header from the data
+    let body = &data[end + 1..];
+    if body.len() < (width * height * 12) as usize {
+        return Err(invalid());
+    }
+    let value = |i: usize| {
+        let bytes = body[i * 4..i * 4 + 4].try_into().unwrap();
+        if scale < 0. {
+            f32::from_le_bytes(bytes)
+        } else {
+            f32::from_be_bytes(bytes)
+        }
+    };
+    Ok(Rgb32FImage::from_fn(width, height, |x, y| {
+        let i = ((height - 1 - y) * width + x) as usize * 3;
+        Rgb([value(i), value(i + 1), value(i + 2)])
+    }))
+}
+
+/// Maps `t` in [0, 1] onto a blue, cyan, green, yellow, red ramp.
+fn heat_colour(t: f64) -> Rgb<u8> {
+    const STOPS: [[f64; 3]; 5] = [
+        [0., 0., 0.5],
+        [0., 0.8, 1.],
+        [0., 0.8, 0.],
+        [1., 1., 0.],
+        [1., 0., 0.],
+    ];
+    let scaled = t.clamp(0., 1.) * (STOPS.len() - 1) as f64;
+    let i = (scaled as usize).min(STOPS.len() - 2);
+    let f = scaled - i as f64;
+    let c = |k: usize| ((STOPS[i][k] * (1. - f) + STOPS[i + 1][k] * f) * 255.) as u8;
+    Rgb([c(0), c(1), c(2)])
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+}
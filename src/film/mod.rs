@@ -0,0 +1,15 @@
+mod aov;
+mod checkpoint;
+mod denoise;
+mod filter;
+mod framebuffer;
+mod pixel_stats;
+mod tone_map;
+
+pub use aov::{Aov, AovBuffer, AovSample, AovStats};
+pub use checkpoint::{Checkpoint, CheckpointError};
+pub use denoise::denoise;
+pub use filter::{Filter, FilterSampler};
+pub use framebuffer::FrameBuffer;
+pub use pixel_stats::PixelStats;
+pub use tone_map::ToneMap;
@@ -0,0 +1,65 @@
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::utility::luminance;
+
+/// Running statistics of the samples taken for one pixel. The colour is the average of the
+/// samples, each multiplied by its weight from the pixel filter. The variance is tracked on
+/// the luminance of the weighted samples with Welford's algorithm so it stays accurate over
+/// many samples.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct PixelStats {
+    weighted_sum: Vector3<f64>,
+    count: u32,
+    mean_luminance: f64,
+    m2_luminance: f64,
+}
+
+impl PixelStats {
+    /// Adds a sample with the filter weight `weight`.
+    pub fn add(&mut self, sample: Vector3<f64>, weight: f64) {
+        let sample = weight * sample;
+        self.weighted_sum += sample;
+        self.count += 1;
+
+        let l = luminance(&sample);
+        let delta = l - self.mean_luminance;
+        self.mean_luminance += delta / self.count as f64;
+        self.m2_luminance += delta * (l - self.mean_luminance);
+    }
+
+    /// Returns the number of samples taken.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns the average of the weighted samples, or black if there are none.
+    pub fn mean(&self) -> Vector3<f64> {
+        if self.count == 0 {
+            Vector3::zeros()
+        } else {
+            self.weighted_sum / self.count as f64
+        }
+    }
+
+    /// Returns the sample variance of the luminance.
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            0.
+        } else {
+            self.m2_luminance / (self.count - 1) as f64
+        }
+    }
+
+    /// Returns true once the noise left in the pixel is below `threshold`. The noise is the
+    /// standard error of the mean luminance carried through a gamma 2 curve, so that dark
+    /// pixels are not held to a stricter standard than the eye can see.
+    pub fn converged(&self, threshold: f64) -> bool {
+        if self.count < 2 {
+            return false;
+        }
+        let standard_error = (self.variance() / self.count as f64).sqrt();
+        let slope = 2. * self.mean_luminance.max(1e-4).sqrt();
+        standard_error / slope < threshold
+    }
+}
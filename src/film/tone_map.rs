@@ -0,0 +1,156 @@
+use clap::ValueEnum;
+use nalgebra::{Matrix3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::utility::luminance;
+
+/// A curve that brings unbounded linear radiance into the [0, 1] range a display can show.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMap {
+    /// Clips anything brighter than white
+    #[default]
+    Clamp,
+    /// Reinhard's L / (1 + L) on luminance, which never quite reaches white
+    Reinhard,
+    /// Reinhard with a white point, above which colours clip to white
+    ExtendedReinhard,
+    /// Stephen Hill's fit of the ACES filmic curve
+    Aces,
+    /// Troy Sobotka's AgX, which desaturates bright colours towards white rather than skewing
+    /// their hue
+    Agx,
+}
+
+impl ToneMap {
+    /// Maps linear radiance to linear display values in [0, 1]. `white_point` is the luminance
+    /// shown as white by [`ToneMap::ExtendedReinhard`], the other curves ignore it.
+    pub fn apply(self, colour: Vector3<f64>, white_point: f64) -> Vector3<f64> {
+        let colour = colour.map(|c| c.max(0.));
+        let mapped = match self {
+            ToneMap::Clamp => colour,
+            ToneMap::Reinhard => scale_luminance(colour, |l| l / (1. + l)),
+            ToneMap::ExtendedReinhard => {
+                let white2 = white_point * white_point;
+                scale_luminance(colour, |l| l * (1. + l / white2) / (1. + l))
+            }
+            ToneMap::Aces => aces(colour),
+            ToneMap::Agx => agx(colour),
+        };
+        mapped.map(|c| c.clamp(0., 1.))
+    }
+}
+
+/// Scales `colour` so its luminance is `curve` of its old luminance, keeping its hue.
+fn scale_luminance(colour: Vector3<f64>, curve: impl Fn(f64) -> f64) -> Vector3<f64> {
+    let l = luminance(&colour);
+    if l <= 0. {
+        colour
+    } else {
+        colour * (curve(l) / l)
+    }
+}
+
+fn aces(colour: Vector3<f64>) -> Vector3<f64> {
+    // Linear sRGB to the ACES working space, with the RRT saturation folded in
+    #[rustfmt::skip]
+    let input = Matrix3::new(
+        0.59719, 0.35458, 0.04823,
+        0.07600, 0.90834, 0.01566,
+        0.02840, 0.13383, 0.83777,
+    );
+    // The ODT saturation and back to linear sRGB
+    #[rustfmt::skip]
+    let output = Matrix3::new(
+        1.60475, -0.53108, -0.07367,
+        -0.10208, 1.10813, -0.00605,
+        -0.00327, -0.07276, 1.07602,
+    );
+
+    let v = input * colour;
+    let a = v.component_mul(&v.add_scalar(0.0245786)).add_scalar(-0.000090537);
+    let b = v.component_mul(&(0.983729 * v).add_scalar(0.4329510)).add_scalar(0.238081);
+    output * a.component_div(&b)
+}
+
+fn agx(colour: Vector3<f64>) -> Vector3<f64> {
+    const MIN_EV: f64 = -12.47393;
+    const MAX_EV: f64 = 4.026069;
+
+    // Linear sRGB to the AgX working space, which is slightly inset towards white
+    #[rustfmt::skip]
+    let inset = Matrix3::new(
+        0.842479062253094, 0.0784335999999992, 0.0792237451477643,
+        0.0423282422610123, 0.878468636469772, 0.0791661274605434,
+        0.0423756549057051, 0.0784336, 0.879142973793104,
+    );
+    #[rustfmt::skip]
+    let outset = Matrix3::new(
+        1.19687900512017, -0.0980208811401368, -0.0990297440797205,
+        -0.0528968517574562, 1.15190312990417, -0.0989611768448433,
+        -0.0529716355144438, -0.0980434501171241, 1.15107367264116,
+    );
+
+    let encoded = (inset * colour).map(|c| {
+        let ev = c.max(1e-10).log2().clamp(MIN_EV, MAX_EV);
+        let x = (ev - MIN_EV) / (MAX_EV - MIN_EV);
+        // A polynomial fit of the AgX base contrast curve
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2
+            + 0.1191 * x
+            - 0.00232
+    });
+
+    // The curve output is display encoded with a 2.2 gamma, so undo that to get linear values
+    (outset * encoded).map(|c| c.max(0.).powf(2.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::vector;
+
+    use super::*;
+
+    const CURVES: [ToneMap; 5] = [
+        ToneMap::Clamp,
+        ToneMap::Reinhard,
+        ToneMap::ExtendedReinhard,
+        ToneMap::Aces,
+        ToneMap::Agx,
+    ];
+
+    #[test]
+    fn curves_rise_from_black_and_stay_displayable() {
+        for curve in CURVES {
+            let black = curve.apply(Vector3::zeros(), 4.);
+            assert!(black.iter().all(|&c| c < 0.01), "{curve:?} lifts black");
+
+            let mut last = black.x;
+            for step in 1..=400 {
+                let grey = curve.apply(Vector3::repeat(step as f64 / 20.), 4.);
+                assert!(grey.iter().all(|c| (0. ..=1.).contains(c)), "{curve:?}");
+                assert!(grey.x >= last, "{curve:?} darkens at {}", step as f64 / 20.);
+                last = grey.x;
+            }
+        }
+    }
+
+    #[test]
+    fn reinhard_maps_luminance_and_keeps_hue() {
+        let grey = ToneMap::Reinhard.apply(Vector3::repeat(1.), 4.);
+        assert!((grey - Vector3::repeat(0.5)).norm() < 1e-12);
+
+        let colour = vector![2., 1., 0.5];
+        let mapped = ToneMap::Reinhard.apply(colour, 4.);
+        assert!((mapped / mapped.x - colour / colour.x).norm() < 1e-12);
+    }
+
+    #[test]
+    fn extended_reinhard_reaches_white_at_the_white_point() {
+        let white = ToneMap::ExtendedReinhard.apply(Vector3::repeat(4.), 4.);
+        assert!((white - Vector3::repeat(1.)).norm() < 1e-12);
+        let below = ToneMap::ExtendedReinhard.apply(Vector3::repeat(3.), 4.);
+        assert!(below.x < 1.);
+    }
+}
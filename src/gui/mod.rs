@@ -1,56 +1,268 @@
 use eframe::egui::load::SizedTexture;
 use eframe::{egui, App};
-use std::sync::{mpsc::Receiver, Arc, Mutex};
+use std::mem;
+use std::sync::mpsc::TryRecvError;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::core::PixelData;
+use image::RgbImage;
 
-pub fn main(receiver: Receiver<PixelData>, image_width: u32, image_height: u32) {
+use crate::{
+    core::{Camera, Hittable, JobStatus, PixelData, RenderJob},
+    film::{self, Aov, AovBuffer, FrameBuffer},
+};
+
+/// How long to wait between denoising the preview while pixels are still arriving.
+const DENOISE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Renders `world` through `cam` in a window, showing the pixels as they arrive, until the
+/// window is closed. A progressive render makes a pass over the image for each sample. `denoise`
+/// is whether the preview starts out denoised. Closing the window or changing the settings in
+/// it cancels the render in progress.
+pub fn main(
+    cam: Arc<Camera>,
+    world: Arc<dyn Hittable + Send + Sync>,
+    progressive: bool,
+    denoise: bool,
+) {
     let options = eframe::NativeOptions::default();
-    let app = MyApp::new(receiver, image_width, image_height);
-    eframe::run_native("Ray Tracing", options, Box::new(|_cc| Box::new(app))).unwrap()
+    let app = MyApp::new(cam, world, progressive, denoise);
+    eframe::run_native("Ray Tracing", options, Box::new(|_cc| Box::new(app))).unwrap();
 }
 
 struct MyApp {
-    reciver: Arc<Mutex<Receiver<PixelData>>>,
+    job: RenderJob,
+    world: Arc<dyn Hittable + Send + Sync>,
+    progressive: bool,
+    /// The settings that can be changed from the window. The render starts again with them
+    /// once they have been changed.
+    samples_per_pixel: u32,
+    max_depth: u32,
+    settings_changed: bool,
     pixels_recieved: u32,
     image_buffer: Vec<u8>,
+    /// The linear colour of each pixel, which is what gets denoised.
+    radiance: FrameBuffer,
+    aovs: AovBuffer,
+    cam: Arc<Camera>,
+    denoise: bool,
+    /// The last denoised preview and when it was made, cleared when new pixels arrive.
+    denoised: Option<(RgbImage, Instant)>,
+    /// Whether pixels have arrived since the preview was last denoised.
+    changed: bool,
+    /// The AOV shown instead of the image, if any.
+    showing: Option<Aov>,
     image_width: u32,
     image_height: u32,
+    pass: u32,
+    passes: u32,
+    /// Whether every pixel the render will send has arrived.
+    finished: bool,
+    show_stats: bool,
 }
 
 impl MyApp {
-    fn new(receiver: Receiver<PixelData>, image_width: u32, image_height: u32) -> Self {
+    fn new(
+        cam: Arc<Camera>,
+        world: Arc<dyn Hittable + Send + Sync>,
+        progressive: bool,
+        denoise: bool,
+    ) -> Self {
+        let (image_width, image_height) = (cam.image_width(), cam.image_height());
+        let passes = if progressive {
+            cam.samples_per_pixel()
+        } else {
+            1
+        };
         Self {
-            reciver: Arc::new(Mutex::new(receiver)),
+            job: start(&cam, &world, progressive),
+            world,
+            progressive,
+            samples_per_pixel: cam.samples_per_pixel(),
+            max_depth: cam.max_depth(),
+            settings_changed: false,
             image_buffer: vec![0; (image_width * image_height * 4) as usize],
+            radiance: FrameBuffer::new(image_width, image_height),
+            aovs: AovBuffer::new(image_width, image_height),
+            cam,
+            denoise,
+            denoised: None,
+            changed: false,
+            showing: None,
             image_width,
             image_height,
             pixels_recieved: 0,
+            pass: 0,
+            passes,
+            finished: false,
+            show_stats: true,
         }
     }
 
+    /// Cancels the render in progress and starts again from a blank image with the settings
+    /// from the window.
+    fn restart(&mut self) {
+        self.job.cancel();
+        let cam = self
+            .cam
+            .to_builder()
+            .samples_per_pixel(self.samples_per_pixel)
+            .max_depth(self.max_depth)
+            .build();
+        let denoise = self.denoise;
+        let (show_stats, showing) = (self.show_stats, self.showing);
+
+        let new = MyApp::new(Arc::new(cam), self.world.clone(), self.progressive, denoise);
+        let old = mem::replace(self, new);
+        self.show_stats = show_stats;
+        self.showing = showing;
+
+        // Dropping the old job waits for the pixels it had started, which can take a while with
+        // many samples per pixel, so it is done away from the window
+        thread::spawn(move || drop(old.job));
+    }
+
     fn update_image(&mut self) {
-        let receiver = self.reciver.lock().unwrap();
-        for data in receiver.try_iter() {
+        loop {
+            let data = match self.job.receiver().try_recv() {
+                Ok(data) => data,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.finished = true;
+                    break;
+                }
+            };
+
             self.pixels_recieved += 1;
-            let PixelData { index, colour } = data;
+            self.changed = true;
+            let PixelData {
+                index,
+                colour,
+                radiance,
+                pass,
+                aovs,
+            } = data;
+            self.radiance.pixels_mut()[index as usize] = radiance;
+            self.aovs.pixels_mut()[index as usize] = aovs;
             let pos = (index * 4) as usize;
             self.image_buffer[pos] = colour.0[0];
             self.image_buffer[pos + 1] = colour.0[1];
             self.image_buffer[pos + 2] = colour.0[2];
             self.image_buffer[pos + 3] = 255;
+
+            // A pass is only shown as complete once its last pixel has arrived
+            if index == self.image_width * self.image_height - 1 {
+                self.pass = self.pass.max(pass);
+            }
+        }
+    }
+
+    /// Returns the denoised preview, filtering it again if pixels have arrived since it was
+    /// last made. While the render is running this happens at most every
+    /// [`DENOISE_INTERVAL`], as filtering the whole image is too slow to do every frame.
+    fn denoised(&mut self) -> &RgbImage {
+        let stale = match &self.denoised {
+            None => true,
+            Some((_, made)) => {
+                self.changed && (self.finished || made.elapsed() >= DENOISE_INTERVAL)
+            }
+        };
+        if stale {
+            let frame = film::denoise(&self.radiance, &self.aovs);
+            self.denoised = Some((self.cam.to_image(&frame), Instant::now()));
+            self.changed = false;
         }
+        &self.denoised.as_ref().expect("denoised above").0
+    }
+
+    fn status(&self) -> String {
+        let pixels = self.image_width * self.image_height;
+        let progress = if self.passes > 1 {
+            format!("Pass {}/{}", self.pass, self.passes)
+        } else {
+            format!("{}/{} pixels", self.pixels_recieved.min(pixels), pixels)
+        };
+
+        match self.job.status() {
+            JobStatus::Running => format!("Rendering: {progress}"),
+            JobStatus::Paused => format!("Paused: {progress}"),
+            JobStatus::Cancelled => format!("Stopped: {progress}"),
+            JobStatus::Finished => format!("Done: {progress}"),
+            JobStatus::Failed => format!("Failed: {progress}"),
+        }
+    }
+}
+
+/// Starts rendering `world` through `cam`.
+fn start(
+    cam: &Arc<Camera>,
+    world: &Arc<dyn Hittable + Send + Sync>,
+    progressive: bool,
+) -> RenderJob {
+    if progressive {
+        cam.clone().render_progressive(world.clone())
+    } else {
+        cam.clone().render_to_channel(world.clone())
     }
 }
 
 impl App for MyApp {
-    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.update_image();
+        egui::TopBottomPanel::top("controls").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(self.status());
+                let status = self.job.status();
+                let active = matches!(status, JobStatus::Running | JobStatus::Paused);
+                if ui.add_enabled(active, egui::Button::new("Stop")).clicked() {
+                    self.job.cancel();
+                }
+                if status == JobStatus::Paused {
+                    if ui.button("Resume").clicked() {
+                        self.job.resume();
+                    }
+                } else if ui.add_enabled(active, egui::Button::new("Pause")).clicked() {
+                    self.job.pause();
+                }
+
+                let samples = ui.add(
+                    egui::DragValue::new(&mut self.samples_per_pixel)
+                        .clamp_range(1..=u32::MAX)
+                        .prefix("Samples: "),
+                );
+                let depth = ui.add(
+                    egui::DragValue::new(&mut self.max_depth)
+                        .clamp_range(1..=u32::MAX)
+                        .prefix("Max depth: "),
+                );
+                // Wait until a value has been let go of, rather than starting again for every
+                // step it is dragged through
+                self.settings_changed |= samples.changed() || depth.changed();
+                if self.settings_changed && !samples.dragged() && !depth.dragged() {
+                    self.restart();
+                }
+
+                ui.checkbox(&mut self.denoise, "Denoise");
+                ui.checkbox(&mut self.show_stats, "Statistics");
+                let name = |showing: Option<Aov>| showing.map_or("image", Aov::name);
+                egui::ComboBox::from_label("Showing")
+                    .selected_text(name(self.showing))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.showing, None, name(None));
+                        for aov in Aov::ALL {
+                            ui.selectable_value(&mut self.showing, Some(aov), name(Some(aov)));
+                        }
+                    });
+            });
+        });
         egui::CentralPanel::default().show(ctx, |ui| {
-            let texture = egui::ColorImage::from_rgba_unmultiplied(
-                [self.image_width as _, self.image_height as _],
-                &self.image_buffer,
-            );
+            let size = [self.image_width as _, self.image_height as _];
+            let texture = match self.showing {
+                Some(aov) => egui::ColorImage::from_rgb(size, &self.aovs.preview(aov)),
+                None if self.denoise => egui::ColorImage::from_rgb(size, self.denoised()),
+                None => egui::ColorImage::from_rgba_unmultiplied(size, &self.image_buffer),
+            };
 
             let texture_handle =
                 ui.ctx()
@@ -59,11 +271,21 @@ impl App for MyApp {
                 texture_handle.id(),
                 egui::vec2(self.image_width as f32, self.image_height as f32),
             );
-            ui.image(t)
+            let image = ui.image(t);
+
+            if self.show_stats {
+                egui::Area::new(egui::Id::new("stats"))
+                    .fixed_pos(image.rect.left_top() + egui::vec2(8., 8.))
+                    .show(ui.ctx(), |ui| {
+                        egui::Frame::popup(ui.style()).show(ui, |ui| {
+                            ui.monospace(self.job.counters().stats().to_string());
+                        });
+                    });
+            }
         });
 
         // While the image is rendering, update every frame
-        if self.pixels_recieved < self.image_width * self.image_height {
+        if !self.finished && self.job.status() != JobStatus::Paused {
             ctx.request_repaint();
         }
     }
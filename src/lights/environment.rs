@@ -0,0 +1,21 @@
+use std::any::Any;
+
+use nalgebra::Vector3;
+
+/// Light arriving from infinitely far away, seen by every ray that leaves the scene. Shading
+/// also samples it directly, so it is picked in proportion to where it is brightest.
+pub trait Environment: Send + Sync {
+    /// Returns the radiance arriving from `direction`, which need not be normalised.
+    fn radiance(&self, direction: &Vector3<f64>) -> Vector3<f64>;
+
+    /// Maps a point in the unit square to a unit direction, returning it with its density over
+    /// solid angle.
+    fn sample(&self, u: [f64; 2]) -> (Vector3<f64>, f64);
+
+    /// Returns the density over solid angle [`Environment::sample`] picks `direction` with.
+    fn pdf(&self, direction: &Vector3<f64>) -> f64;
+
+    /// Returns this environment as [`Any`] so that its concrete type can be recovered, which is
+    /// how scenes are exported.
+    fn as_any(&self) -> &dyn Any;
+}
@@ -0,0 +1,107 @@
+use std::{
+    any::Any,
+    path::{Path, PathBuf},
+};
+
+use image::ImageResult;
+use nalgebra::{vector, Vector3};
+
+use crate::{film::FrameBuffer, utility::luminance};
+
+use super::{
+    lat_long::{direction_to_uv, LatLongDistribution},
+    Environment,
+};
+
+/// An environment lit by an equirectangular image, usually an HDR photograph of a real sky.
+pub struct EnvironmentMap {
+    image: FrameBuffer,
+    path: PathBuf,
+    /// The rotation about the y axis in degrees.
+    rotation: f64,
+    /// The scale applied to the radiance in the image.
+    intensity: f64,
+    sin_rotation: f64,
+    cos_rotation: f64,
+    distribution: LatLongDistribution,
+}
+
+impl EnvironmentMap {
+    /// Loads an environment map from an `.exr`, `.hdr` or `.pfm` image, or any other format
+    /// the `image` crate reads.
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        rotation: f64,
+        intensity: f64,
+    ) -> ImageResult<EnvironmentMap> {
+        let image = FrameBuffer::open(&path)?;
+        let func: Vec<f64> = image.pixels().iter().map(luminance).collect();
+        let distribution = LatLongDistribution::new(&func, image.width() as usize);
+
+        let (sin_rotation, cos_rotation) = rotation.to_radians().sin_cos();
+        Ok(EnvironmentMap {
+            image,
+            path: path.as_ref().to_owned(),
+            rotation,
+            intensity,
+            sin_rotation,
+            cos_rotation,
+            distribution,
+        })
+    }
+
+    /// Returns the path the image was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the rotation about the y axis in degrees.
+    pub fn rotation(&self) -> f64 {
+        self.rotation
+    }
+
+    /// Returns the scale applied to the radiance in the image.
+    pub fn intensity(&self) -> f64 {
+        self.intensity
+    }
+
+    /// Turns a direction in the scene into one in the frame of the image.
+    fn to_local(&self, d: &Vector3<f64>) -> Vector3<f64> {
+        vector![
+            self.cos_rotation * d.x - self.sin_rotation * d.z,
+            d.y,
+            self.sin_rotation * d.x + self.cos_rotation * d.z
+        ]
+    }
+
+    /// Turns a direction in the frame of the image into one in the scene.
+    fn to_world(&self, d: &Vector3<f64>) -> Vector3<f64> {
+        vector![
+            self.cos_rotation * d.x + self.sin_rotation * d.z,
+            d.y,
+            -self.sin_rotation * d.x + self.cos_rotation * d.z
+        ]
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: &Vector3<f64>) -> Vector3<f64> {
+        let [u, v] = direction_to_uv(&self.to_local(direction));
+        let i = ((u * self.image.width() as f64) as u32).min(self.image.width() - 1);
+        let j = ((v * self.image.height() as f64) as u32).min(self.image.height() - 1);
+        self.intensity * self.image.get(i, j)
+    }
+
+    fn sample(&self, u: [f64; 2]) -> (Vector3<f64>, f64) {
+        let (direction, pdf) = self.distribution.sample(u);
+        (self.to_world(&direction), pdf)
+    }
+
+    fn pdf(&self, direction: &Vector3<f64>) -> f64 {
+        self.distribution.pdf(&self.to_local(direction))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
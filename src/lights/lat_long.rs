@@ -0,0 +1,74 @@
+use std::f64::consts::PI;
+
+use nalgebra::{vector, Vector3};
+
+use crate::sampling::Distribution2d;
+
+/// Returns where `direction` lands on an equirectangular image, as fractions across and down
+/// it. Longitude runs across the image and latitude down it, with straight up along the top
+/// row, the same mapping [`crate::shapes::Sphere`] gives image textures.
+pub(super) fn direction_to_uv(direction: &Vector3<f64>) -> [f64; 2] {
+    let d = direction.normalize();
+    let phi = f64::atan2(-d.z, d.x) + PI;
+    let theta = d.y.clamp(-1., 1.).acos();
+    [phi / (2. * PI), theta / PI]
+}
+
+/// Returns the unit direction that lands at `[u, v]` on an equirectangular image.
+pub(super) fn uv_to_direction([u, v]: [f64; 2]) -> Vector3<f64> {
+    let phi = 2. * PI * u - PI;
+    let (sin_theta, cos_theta) = (PI * v).sin_cos();
+    vector![sin_theta * phi.cos(), cos_theta, -sin_theta * phi.sin()]
+}
+
+/// Picks directions in proportion to a function tabulated over an equirectangular grid.
+pub(super) struct LatLongDistribution {
+    distribution: Distribution2d,
+}
+
+impl LatLongDistribution {
+    /// Creates a distribution from `func`, which holds `width` values for each row from the
+    /// top. Rows near the poles are squeezed into less solid angle, so they are weighted less.
+    pub(super) fn new(func: &[f64], width: usize) -> LatLongDistribution {
+        let height = func.len() / width;
+        let weighted: Vec<f64> = func
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let theta = PI * ((i / width) as f64 + 0.5) / height as f64;
+                value.max(0.) * theta.sin()
+            })
+            .collect();
+        LatLongDistribution {
+            distribution: Distribution2d::new(&weighted, width),
+        }
+    }
+
+    /// Returns the integral of the function over the sphere.
+    pub(super) fn integral(&self) -> f64 {
+        self.distribution.integral() * 2. * PI * PI
+    }
+
+    /// Maps a point in the unit square to a unit direction, returning it with its density over
+    /// solid angle.
+    pub(super) fn sample(&self, u: [f64; 2]) -> (Vector3<f64>, f64) {
+        let (uv, pdf) = self.distribution.sample(u);
+        let direction = uv_to_direction(uv);
+        (direction, solid_angle_pdf(pdf, uv))
+    }
+
+    /// Returns the density over solid angle of `direction`.
+    pub(super) fn pdf(&self, direction: &Vector3<f64>) -> f64 {
+        let uv = direction_to_uv(direction);
+        solid_angle_pdf(self.distribution.pdf(uv), uv)
+    }
+}
+
+/// Converts a density over the image to one over solid angle.
+fn solid_angle_pdf(pdf: f64, [_, v]: [f64; 2]) -> f64 {
+    let sin_theta = (PI * v).sin();
+    if sin_theta <= 0. {
+        return 0.;
+    }
+    pdf / (2. * PI * PI * sin_theta)
+}
@@ -0,0 +1,150 @@
+use std::{any::Any, sync::Arc};
+
+use nalgebra::{Point3, Vector3};
+
+use crate::{
+    core::{Hittable, HittableList},
+    materials::Material,
+    shapes::{BvhNode, Quad, Sphere},
+    wrappers::{RotateY, Translate},
+};
+
+/// The emissive objects in a scene, which shading samples directly rather than waiting for a
+/// bounce to happen upon them. Only quads and spheres can be sampled, including those in moved
+/// or rotated groups, which are sampled through the same moves and rotations.
+#[derive(Default)]
+pub struct LightList {
+    lights: Vec<Arc<dyn Hittable>>,
+}
+
+impl LightList {
+    /// Finds every emissive object in `world`.
+    pub fn new(world: &dyn Hittable) -> LightList {
+        let mut lights = vec![];
+        for child in children(world) {
+            gather(child, &|light| light, &mut lights);
+        }
+        LightList { lights }
+    }
+
+    /// Returns whether the scene has no lights that can be sampled.
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// Maps a point in the unit square to a direction from `origin` towards one of the lights,
+    /// each of which is picked equally often.
+    pub fn sample(&self, origin: &Point3<f64>, [u1, u2]: [f64; 2], time: f64) -> Vector3<f64> {
+        let scaled = u1 * self.lights.len() as f64;
+        let index = (scaled as usize).min(self.lights.len() - 1);
+        self.lights[index].random(origin, [scaled - index as f64, u2], time)
+    }
+
+    /// Returns the density over solid angle with which [`LightList::sample`] picks `direction`
+    /// from `origin`. Where this is zero no light was sampled, so light arriving that way has to
+    /// be found by bouncing.
+    pub fn pdf(&self, origin: &Point3<f64>, direction: &Vector3<f64>, time: f64) -> f64 {
+        if self.lights.is_empty() {
+            return 0.;
+        }
+        let sum: f64 = self
+            .lights
+            .iter()
+            .map(|light| light.pdf_value(origin, direction, time))
+            .sum();
+        sum / self.lights.len() as f64
+    }
+}
+
+/// Adds `object` to `lights` if it can be sampled and emits light, or otherwise looks for
+/// lights among its children. `place` puts a light found where the groups it is in have moved
+/// it, so that only the light is sampled and not the rest of its group.
+fn gather(
+    object: &Arc<dyn Hittable>,
+    place: &dyn Fn(Arc<dyn Hittable>) -> Arc<dyn Hittable>,
+    lights: &mut Vec<Arc<dyn Hittable>>,
+) {
+    let any = object.as_any();
+    if let Some(translate) = any.downcast_ref::<Translate>() {
+        let offset = translate.offset();
+        let place = |light| place(Arc::new(Translate::animated(light, offset.clone())));
+        gather(translate.object(), &place, lights);
+    } else if let Some(rotate) = any.downcast_ref::<RotateY>() {
+        let place = |light| place(Arc::new(RotateY::animated(light, rotate.angle().clone())));
+        gather(rotate.object(), &place, lights);
+    } else if emits(any) {
+        lights.push(place(object.clone()));
+    } else {
+        for child in children(object.as_ref()) {
+            gather(child, place, lights);
+        }
+    }
+}
+
+/// Returns the objects grouped under `object`, counting each only once.
+fn children(object: &dyn Hittable) -> Vec<&Arc<dyn Hittable>> {
+    let any = object.as_any();
+    if let Some(list) = any.downcast_ref::<HittableList>() {
+        list.objects.iter().collect()
+    } else if let Some(node) = any.downcast_ref::<BvhNode>() {
+        let (left, right) = node.children();
+        if Arc::ptr_eq(left, right) {
+            vec![left]
+        } else {
+            vec![left, right]
+        }
+    } else {
+        vec![]
+    }
+}
+
+/// Returns whether `object` can be sampled and emits light.
+fn emits(object: &dyn Any) -> bool {
+    if let Some(quad) = object.downcast_ref::<Quad>() {
+        matches!(quad.material(), Material::DiffuseLight { .. })
+    } else if let Some(sphere) = object.downcast_ref::<Sphere>() {
+        matches!(sphere.material(), Material::DiffuseLight { .. })
+    } else {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{point, vector};
+
+    use super::*;
+    use crate::materials::SolidColour;
+
+    #[test]
+    fn only_the_emitting_parts_of_moved_groups_are_sampled() {
+        let light = Material::DiffuseLight {
+            emit: Arc::new(SolidColour::new(vector![1., 1., 1.])),
+        };
+        let wall = Material::Lambertian {
+            albedo: Arc::new(SolidColour::new(vector![0.5, 0.5, 0.5])),
+        };
+        let mut group = HittableList::new();
+        let (corner, x, z) = (point![0., 1., 0.], vector![1., 0., 0.], vector![0., 0., 1.]);
+        group.add(Box::new(Quad::new(corner, x, z, &light)));
+        group.add(Box::new(Sphere::new(point![5., 0., 0.], 1., &wall)));
+        let mut world = HittableList::new();
+        let group = RotateY::new(Arc::new(BvhNode::new(&group.objects)), 0.);
+        world.add(Box::new(Translate::new(
+            Arc::new(group),
+            vector![0., 2., 0.],
+        )));
+
+        let lights = LightList::new(&world);
+        assert_eq!(lights.lights.len(), 1);
+        let origin = point![0.5, 0., 0.5];
+        assert!(lights.pdf(&origin, &vector![0., 1., 0.], 0.) > 0.);
+        assert_eq!(lights.pdf(&origin, &vector![4.5, 2., -0.5], 0.), 0.);
+        for u in [[0.1, 0.2], [0.5, 0.5], [0.9, 0.7]] {
+            // Every sample is aimed at the light, which has been moved up to y = 3
+            let direction = lights.sample(&origin, u, 0.);
+            let hit = origin + direction * (3. / direction.y);
+            assert!((0. ..=1.).contains(&hit.x) && (0. ..=1.).contains(&hit.z));
+        }
+    }
+}
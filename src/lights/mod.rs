@@ -0,0 +1,10 @@
+mod environment;
+mod environment_map;
+mod lat_long;
+mod light_list;
+mod sky;
+
+pub use environment::Environment;
+pub use environment_map::EnvironmentMap;
+pub use light_list::LightList;
+pub use sky::Sky;
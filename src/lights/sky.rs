@@ -0,0 +1,263 @@
+use std::{any::Any, f64::consts::PI};
+
+use nalgebra::{vector, Matrix3, Vector3};
+
+use crate::{
+    sampling::{square_to_cone, to_world},
+    utility::luminance,
+};
+
+use super::{
+    lat_long::{uv_to_direction, LatLongDistribution},
+    Environment,
+};
+
+/// The angular radius of the sun in radians.
+const SUN_RADIUS: f64 = 0.004_65;
+/// The luminance of the sun outside the atmosphere, in the kcd/m² the sky model uses.
+const SUN_LUMINANCE: f64 = 2e6;
+/// The scale from kcd/m² to radiance, which makes a clear sky around 1.
+const SCALE: f64 = 0.1;
+/// The size of the table the sky is sampled from.
+const TABLE_WIDTH: usize = 64;
+const TABLE_HEIGHT: usize = 32;
+
+/// A clear daylight sky using the Preetham model, with the sun as a small bright disk in it.
+/// Below the horizon the sky is black, so scenes need ground of their own.
+pub struct Sky {
+    /// The height of the sun above the horizon in degrees.
+    sun_elevation: f64,
+    /// The direction of the sun around the horizon in degrees, turning from -z towards +x.
+    sun_azimuth: f64,
+    /// How hazy the air is, from 2 for a very clear sky to 10 for a hazy one.
+    turbidity: f64,
+    /// The scale applied to the radiance of the sky and sun.
+    intensity: f64,
+    sun_direction: Vector3<f64>,
+    /// The radiance of the sun disk after passing through the atmosphere.
+    sun_radiance: Vector3<f64>,
+    /// The Perez coefficients for the luminance and the x and y chromaticities.
+    perez: [[f64; 5]; 3],
+    /// The luminance and chromaticities at the zenith, divided by the Perez function there.
+    zenith: [f64; 3],
+    sky_distribution: LatLongDistribution,
+    /// The chance of sampling the sun rather than the sky.
+    sun_probability: f64,
+}
+
+impl Sky {
+    pub fn new(sun_elevation: f64, sun_azimuth: f64, turbidity: f64, intensity: f64) -> Sky {
+        let (sin_elevation, cos_elevation) = sun_elevation.to_radians().sin_cos();
+        let (sin_azimuth, cos_azimuth) = sun_azimuth.to_radians().sin_cos();
+        let sun_direction = vector![
+            cos_elevation * sin_azimuth,
+            sin_elevation,
+            -cos_elevation * cos_azimuth
+        ];
+
+        let t = turbidity;
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        // The zenith values from the paper, which are fits in the sun's angle from the zenith
+        let theta_s = (PI / 2. - sun_elevation.to_radians()).clamp(0., PI / 2.);
+        let chi = (4. / 9. - t / 120.) * (PI - 2. * theta_s);
+        let zenith_luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.);
+        let cubic = |c: [f64; 4]| ((c[0] * theta_s + c[1]) * theta_s + c[2]) * theta_s + c[3];
+        let zenith_chromaticity = |a, b, c| t * t * cubic(a) + t * cubic(b) + cubic(c);
+        let zenith_x = zenith_chromaticity(
+            [0.00166, -0.00375, 0.00209, 0.],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        );
+        let zenith_y = zenith_chromaticity(
+            [0.00275, -0.00610, 0.00317, 0.],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        );
+        let zenith = [
+            zenith_luminance / perez_function(&perez[0], 0., theta_s),
+            zenith_x / perez_function(&perez[1], 0., theta_s),
+            zenith_y / perez_function(&perez[2], 0., theta_s),
+        ];
+
+        let sun_radiance = sun_radiance(turbidity, theta_s) * (SCALE * intensity);
+
+        let func: Vec<f64> = (0..TABLE_WIDTH * TABLE_HEIGHT)
+            .map(|i| {
+                let u = ((i % TABLE_WIDTH) as f64 + 0.5) / TABLE_WIDTH as f64;
+                let v = ((i / TABLE_WIDTH) as f64 + 0.5) / TABLE_HEIGHT as f64;
+                let direction = uv_to_direction([u, v]);
+                sky_xyy(&perez, &zenith, &sun_direction, &direction)[0]
+            })
+            .collect();
+        let sky_distribution = LatLongDistribution::new(&func, TABLE_WIDTH);
+
+        // Share the samples between the sky and the sun by how much light each gives
+        let sun_power = if sun_elevation > 0. {
+            luminance(&sun_radiance) * 2. * PI * (1. - SUN_RADIUS.cos())
+        } else {
+            0.
+        };
+        let sky_power = sky_distribution.integral() * SCALE * intensity;
+        let sun_probability = if sun_power > 0. {
+            (sun_power / (sun_power + sky_power)).min(0.9)
+        } else {
+            0.
+        };
+
+        Sky {
+            sun_elevation,
+            sun_azimuth,
+            turbidity,
+            intensity,
+            sun_direction,
+            sun_radiance,
+            perez,
+            zenith,
+            sky_distribution,
+            sun_probability,
+        }
+    }
+
+    /// Returns the height of the sun above the horizon in degrees.
+    pub fn sun_elevation(&self) -> f64 {
+        self.sun_elevation
+    }
+
+    /// Returns the direction of the sun around the horizon in degrees.
+    pub fn sun_azimuth(&self) -> f64 {
+        self.sun_azimuth
+    }
+
+    /// Returns how hazy the air is.
+    pub fn turbidity(&self) -> f64 {
+        self.turbidity
+    }
+
+    /// Returns the scale applied to the radiance of the sky and sun.
+    pub fn intensity(&self) -> f64 {
+        self.intensity
+    }
+
+    fn sky_radiance(&self, direction: &Vector3<f64>) -> Vector3<f64> {
+        let [luminance, x, y] = sky_xyy(&self.perez, &self.zenith, &self.sun_direction, direction);
+        if luminance <= 0. || y <= 0. {
+            return Vector3::zeros();
+        }
+        let xyz = vector![x * luminance / y, luminance, (1. - x - y) * luminance / y];
+        (xyz_to_srgb() * xyz).map(|c| c.max(0.)) * (SCALE * self.intensity)
+    }
+
+    fn in_sun(&self, direction: &Vector3<f64>) -> bool {
+        self.sun_elevation > 0. && direction.dot(&self.sun_direction) >= SUN_RADIUS.cos()
+    }
+}
+
+impl Environment for Sky {
+    fn radiance(&self, direction: &Vector3<f64>) -> Vector3<f64> {
+        let direction = direction.normalize();
+        let sky = self.sky_radiance(&direction);
+        if self.in_sun(&direction) {
+            sky + self.sun_radiance
+        } else {
+            sky
+        }
+    }
+
+    fn sample(&self, [u1, u2]: [f64; 2]) -> (Vector3<f64>, f64) {
+        let direction = if u1 < self.sun_probability {
+            // Uniformly over the cone the sun fills
+            let u1 = u1 / self.sun_probability;
+            let local = square_to_cone([u1, u2], SUN_RADIUS.cos());
+            to_world(&local, &self.sun_direction)
+        } else {
+            let u1 = (u1 - self.sun_probability) / (1. - self.sun_probability);
+            self.sky_distribution.sample([u1, u2]).0
+        };
+        (direction, self.pdf(&direction))
+    }
+
+    fn pdf(&self, direction: &Vector3<f64>) -> f64 {
+        let direction = direction.normalize();
+        let sky_pdf = self.sky_distribution.pdf(&direction);
+        let sun_pdf = if self.in_sun(&direction) {
+            1. / (2. * PI * (1. - SUN_RADIUS.cos()))
+        } else {
+            0.
+        };
+        self.sun_probability * sun_pdf + (1. - self.sun_probability) * sky_pdf
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Returns the luminance and x and y chromaticities of the sky towards the unit `direction`.
+fn sky_xyy(
+    perez: &[[f64; 5]; 3],
+    zenith: &[f64; 3],
+    sun_direction: &Vector3<f64>,
+    direction: &Vector3<f64>,
+) -> [f64; 3] {
+    if direction.y <= 0. {
+        return [0.; 3];
+    }
+    let theta = direction.y.min(1.).acos();
+    let gamma = direction.dot(sun_direction).clamp(-1., 1.).acos();
+    [0, 1, 2].map(|i| zenith[i] * perez_function(&perez[i], theta, gamma))
+}
+
+/// The Perez sky function at `theta` from the zenith and `gamma` from the sun.
+fn perez_function([a, b, c, d, e]: &[f64; 5], theta: f64, gamma: f64) -> f64 {
+    let cos_theta = theta.cos().max(1e-3);
+    let cos_gamma = gamma.cos();
+    (1. + a * (b / cos_theta).exp()) * (1. + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
+
+/// The sun's colour after passing through the atmosphere at `theta_s` from the zenith, from
+/// Rayleigh scattering by the air and Ångström's model of scattering by haze. Each channel
+/// uses a single wavelength.
+fn sun_radiance(turbidity: f64, theta_s: f64) -> Vector3<f64> {
+    // Kasten and Young's air mass, which stays finite at the horizon
+    let air_mass = 1. / (theta_s.cos() + 0.50572 * (96.07995 - theta_s.to_degrees()).powf(-1.6364));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let wavelengths = vector![0.680, 0.550, 0.440];
+    wavelengths.map(|lambda: f64| {
+        let rayleigh = 0.008735 * lambda.powf(-4.08);
+        let haze = beta * lambda.powf(-1.3);
+        SUN_LUMINANCE * (-air_mass * (rayleigh + haze)).exp()
+    })
+}
+
+fn xyz_to_srgb() -> Matrix3<f64> {
+    #[rustfmt::skip]
+    let matrix = Matrix3::new(
+        3.2406, -1.5372, -0.4986,
+        -0.9689, 1.8758, 0.0415,
+        0.0557, -0.2040, 1.0570,
+    );
+    matrix
+}
@@ -1,25 +1,274 @@
 pub mod core;
+pub mod film;
 pub mod utility;
 pub mod wrappers;
 pub mod materials;
+pub mod sampling;
 pub mod scenes;
 pub mod shapes;
+pub mod animation;
+pub mod lights;
+mod cli;
+mod distributed;
 mod gui;
 
 extern crate nalgebra as na;
 
-use std::sync::Arc;
+use std::{
+    path::Path,
+    process,
+    sync::Arc,
+    time::Duration,
+};
 
+use clap::Parser;
 pub use na::{Point3, Vector3};
+use crate::core::{Camera, Hittable, RenderCounters};
+use film::{AovBuffer, Checkpoint, FrameBuffer};
+use lights::{EnvironmentMap, Sky};
 use shapes::BvhNode;
 
+use cli::{Args, Mode};
+
 fn main() {
-    // let (world, cam) = scenes::final_scene(800, 1000, 40);
-    let (world, cam) = scenes::cornel_box();
+    let args = Args::parse();
+    let exr = args.output.extension().is_some_and(|e| e.eq_ignore_ascii_case("exr"));
+    if args.aov_layers && !exr {
+        eprintln!("--aov-layers needs the output to be an .exr");
+        process::exit(1);
+    }
+
+    let (world, cam) = match &args.file {
+        Some(path) => scenes::load_scene(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load {}: {e}", path.display());
+            process::exit(1);
+        }),
+        None => {
+            let scene = scenes::by_name(&args.scene).expect("scene name is checked by the parser");
+            scene(args.seed.unwrap_or(0))
+        }
+    };
+    let mut builder = args.camera(cam.to_builder());
+    if let Some(path) = &args.environment {
+        let map = EnvironmentMap::new(path, args.environment_rotation, args.environment_intensity)
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to read {}: {e}", path.display());
+                process::exit(1);
+            });
+        builder = builder.environment(Arc::new(map));
+    }
+    if args.sky {
+        let sky = Sky::new(
+            args.sun_elevation,
+            args.sun_azimuth,
+            args.turbidity,
+            args.environment_intensity,
+        );
+        builder = builder.environment(Arc::new(sky));
+    }
+    // The options are checked one at a time as they are parsed, but some only go wrong together
+    // with the rest of the camera
+    if let Err((name, message)) = builder.check() {
+        eprintln!("Invalid camera setting `{name}`: {message}");
+        process::exit(1);
+    }
+    let cam = builder.build();
+
+    if let Some(path) = &args.export {
+        if let Err(e) = scenes::save_scene(path, &world, &cam) {
+            eprintln!("Failed to export to {}: {e}", path.display());
+            process::exit(1);
+        }
+        return;
+    }
+
+    // Checkpoints and workers hold a fingerprint of the scene so that they are only used with
+    // the same one. Finding it means describing the whole scene, so it is skipped otherwise
+    let fingerprint = match (&args.checkpoint, args.mode) {
+        (Some(_), Mode::Headless) | (_, Mode::Coordinator | Mode::Worker) => {
+            let fingerprint = scenes::scene_fingerprint(&world, &cam).unwrap_or_else(|e| {
+                eprintln!("Failed to fingerprint the scene: {e}");
+                process::exit(1);
+            });
+            Some(fingerprint)
+        }
+        _ => None,
+    };
+
     let nodes = BvhNode::new(&world.objects);
-    
-    let reciever = Arc::new(cam).render_to_channel(Arc::new(nodes));
 
-    gui::main(reciever, 600, 600);
+    match args.mode {
+        Mode::Gui => gui::main(Arc::new(cam), Arc::new(nodes), args.progressive, args.denoise),
+        Mode::Headless => match args.frames {
+            None => {
+                let checkpoint = args.checkpoint.as_deref().zip(fingerprint);
+                let heatmap = args.heatmap.as_deref();
+                render_image(&cam, &nodes, &args, &args.output, heatmap, checkpoint);
+            }
+            Some(frames) => {
+                let first = cam.frame();
+                let builder = cam.to_builder();
+                for frame in first..first + frames {
+                    println!("Frame {frame}");
+                    let cam = builder.clone().frame(frame).build();
+                    let output = cli::frame_path(&args.output, frame);
+                    let heatmap = args
+                        .heatmap
+                        .as_deref()
+                        .map(|path| cli::frame_path(path, frame));
+                    let checkpoint = args
+                        .checkpoint
+                        .as_deref()
+                        .map(|path| cli::frame_path(path, frame));
+                    let checkpoint = checkpoint.as_deref().zip(fingerprint);
+                    render_image(&cam, &nodes, &args, &output, heatmap.as_deref(), checkpoint);
+                }
+            }
+        },
+        Mode::Coordinator => {
+            if args.frames.is_some() {
+                eprintln!("--frames can't be used with a coordinator");
+                process::exit(1);
+            }
+            let scene = fingerprint.expect("fingerprint is found for coordinators");
+            let aovs = !args.aovs.is_empty() || args.denoise;
+            let timeout = Duration::from_secs_f64(args.worker_timeout);
+            let checkpoint =
+                distributed::coordinate(&args.address, &cam, scene, aovs, args.tile_size, timeout)
+                    .unwrap_or_else(|e| {
+                        eprintln!("Failed to coordinate on {}: {e}", args.address);
+                        process::exit(1);
+                    });
+            let (frame, aov_buffer) = (checkpoint.frame(), checkpoint.aov_buffer());
+            let heatmap = args.heatmap.as_deref();
+            write_image(&cam, frame, aov_buffer, &args, &args.output, heatmap);
+        }
+        Mode::Worker => {
+            let scene = fingerprint.expect("fingerprint is found for workers");
+            match distributed::work(&args.address, &nodes, &cam, scene) {
+                Ok(stats) => println!("{stats}"),
+                Err(e) => {
+                    eprintln!("Failed to work for {}: {e}", args.address);
+                    process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+/// Renders a single image in headless mode and writes it to `output`, exiting on failure. The
+/// AOVs, denoising and reference image are taken from `args`, with the AOVs written alongside
+/// the image or into it as layers. Statistics about the render are printed once it is done.
+/// With `checkpoint`, a file and a fingerprint of the scene, the progress is saved to the file
+/// as the render goes.
+fn render_image(
+    cam: &Camera,
+    world: &dyn Hittable,
+    args: &Args,
+    output: &Path,
+    heatmap: Option<&Path>,
+    checkpoint: Option<(&Path, u64)>,
+) {
+    let record_aovs = !args.aovs.is_empty() || args.denoise;
+
+    let counters = RenderCounters::new();
+    let (frame, aov_buffer) = match checkpoint {
+        Some((path, scene)) => render_checkpointed(cam, world, args, path, scene, &counters),
+        None => cam.render_film(world, record_aovs, &counters),
+    };
+    println!("{}", counters.stats());
+    write_image(cam, frame, aov_buffer, args, output, heatmap);
+}
+
+/// Writes a rendered image to `output`, denoising it first if `--denoise` is set, and writes
+/// the AOVs, the sample heatmap and the error against `--reference` that `args` ask for.
+fn write_image(
+    cam: &Camera,
+    mut frame: FrameBuffer,
+    aov_buffer: Option<AovBuffer>,
+    args: &Args,
+    output: &Path,
+    heatmap: Option<&Path>,
+) {
+    let (aovs, aov_layers, denoise) = (&args.aovs, args.aov_layers, args.denoise);
+    if let (Some(aov_buffer), true) = (&aov_buffer, denoise) {
+        frame = film::denoise(&frame, aov_buffer);
+    }
+    let saved = match &aov_buffer {
+        Some(aov_buffer) if aov_layers => aov_buffer.save_layers(output, &frame, aovs),
+        _ => cam.save(&frame, output),
+    };
+    if let Err(e) = saved {
+        eprintln!("Failed to write {}: {e}", output.display());
+        process::exit(1);
+    }
+    if let (Some(aov_buffer), false) = (&aov_buffer, aov_layers) {
+        for &aov in aovs {
+            let path = cli::aov_path(output, aov);
+            if let Err(e) = aov_buffer.layer(aov).save(&path) {
+                eprintln!("Failed to write {}: {e}", path.display());
+                process::exit(1);
+            }
+        }
+    }
+    if let Some(path) = &args.reference {
+        let reference = FrameBuffer::open(path).unwrap_or_else(|e| {
+            eprintln!("Failed to read {}: {e}", path.display());
+            process::exit(1);
+        });
+        match frame.rmse(&reference) {
+            Some(rmse) => println!("RMSE against {}: {rmse:.6}", path.display()),
+            None => {
+                eprintln!("{} is not the same size as the render", path.display());
+                process::exit(1);
+            }
+        }
+    }
+    if let Some(path) = heatmap {
+        if let Err(e) = frame.sample_heatmap().save(path) {
+            eprintln!("Failed to write {}: {e}", path.display());
+            process::exit(1);
+        }
+    }
+}
+
+/// Renders with [`Camera::render_from`], saving the progress to `path` every
+/// `--checkpoint-interval` seconds and once the render is done. With `--resume` the render is
+/// carried on from `path`, exiting if it is not of the scene with the fingerprint `scene` or
+/// with the same camera settings.
+fn render_checkpointed(
+    cam: &Camera,
+    world: &dyn Hittable,
+    args: &Args,
+    path: &Path,
+    scene: u64,
+    counters: &RenderCounters,
+) -> (FrameBuffer, Option<AovBuffer>) {
+    let aovs = !args.aovs.is_empty() || args.denoise;
+    let mut checkpoint = if args.resume {
+        let size = (cam.image_width(), cam.image_height());
+        Checkpoint::open(path)
+            .and_then(|checkpoint| {
+                checkpoint.check(size, scene, cam.fingerprint(), aovs)?;
+                Ok(checkpoint)
+            })
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to resume from {}: {e}", path.display());
+                process::exit(1);
+            })
+    } else {
+        cam.checkpoint(scene, aovs)
+    };
+
+    // A checkpoint that fails to save is not worth stopping the render for
+    let save = |checkpoint: &Checkpoint| {
+        if let Err(e) = checkpoint.save(path) {
+            eprintln!("Failed to save checkpoint {}: {e}", path.display());
+        }
+    };
+    let interval = Duration::from_secs_f64(args.checkpoint_interval);
+    cam.render_from(world, &mut checkpoint, counters, interval, &save);
+    save(&checkpoint);
 
+    (checkpoint.frame(), checkpoint.aov_buffer())
 }
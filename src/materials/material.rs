@@ -1,12 +1,21 @@
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
 use nalgebra::{Vector3, Point3, vector};
-use rand::random;
-
-use crate::{core::{HitRecord, Ray}, utility::random::rng_unit_vec};
+use crate::{core::{HitRecord, Ray}, sampling::{self, square_to_sphere}};
 
 use super::textures::Texture;
 
+/// A direction picked by [`Material::scatter`].
+pub struct Scatter {
+    pub ray: Ray,
+    /// What the light arriving back along `ray` is multiplied by: [`Material::eval`] divided by
+    /// `pdf` for directions that are sampled.
+    pub attenuation: Vector3<f64>,
+    /// The density over solid angle the direction was picked with, or `None` if it was the only
+    /// direction the material could scatter in, as with glass or a perfect mirror.
+    pub pdf: Option<f64>,
+}
+
 #[derive(Clone)]
 pub enum Material {
     Lambertian { albedo: Arc<dyn Texture> },
@@ -24,27 +33,92 @@ impl Material {
         }
     }
 
-    pub fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Ray, Vector3<f64>)> {
+    /// Returns the colour of the material at the hit, without any lighting. Lights give their
+    /// emission clamped to one, and glass is white.
+    pub fn albedo(&self, rec: &HitRecord) -> Vector3<f64> {
+        match self {
+            Self::Lambertian { albedo } | Self::Isotropic { albedo } => {
+                albedo.value(rec.u, rec.v, rec.point)
+            }
+            Self::Metal { albedo, .. } => *albedo,
+            Self::Dielectric { .. } => vector![1., 1., 1.],
+            Self::DiffuseLight { emit } => emit.value(rec.u, rec.v, rec.point).map(|c| c.min(1.)),
+        }
+    }
+
+    /// Returns whether the material only ever scatters light in one direction, so that there is
+    /// no point sampling lights from it.
+    pub fn is_specular(&self) -> bool {
+        match self {
+            Self::Dielectric { .. } => true,
+            Self::Metal { fuzz, .. } => *fuzz == 0.,
+            _ => false,
+        }
+    }
+
+    /// Returns how much of the light arriving from `direction` is scattered back along
+    /// `ray_in`, including the cosine at surfaces. Specular materials return zero, as the chance
+    /// of any given direction being the one they scatter in is nil.
+    pub fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vector3<f64>) -> Vector3<f64> {
+        match self {
+            Self::Lambertian { albedo } => {
+                let cosine = direction.dot(&rec.normal) / direction.norm();
+                albedo.value(rec.u, rec.v, rec.point) * (cosine.max(0.) / PI)
+            }
+            Self::Metal { albedo, .. } => *albedo * self.pdf(ray_in, rec, direction),
+            Self::Isotropic { albedo } => albedo.value(rec.u, rec.v, rec.point) / (4. * PI),
+            Self::Dielectric { .. } | Self::DiffuseLight { .. } => Vector3::zeros(),
+        }
+    }
+
+    /// Returns the density over solid angle with which [`Material::scatter`] picks `direction`,
+    /// or zero for specular materials.
+    pub fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vector3<f64>) -> f64 {
+        match self {
+            Self::Lambertian { .. } => {
+                let cosine = direction.dot(&rec.normal) / direction.norm();
+                cosine.max(0.) / PI
+            }
+            Self::Metal { fuzz, .. } => {
+                if *fuzz == 0. || direction.dot(&rec.normal) <= 0. {
+                    return 0.;
+                }
+                let reflected = reflect(&ray_in.direction().normalize(), &rec.normal);
+                fuzzed_pdf(&reflected, *fuzz, &direction.normalize())
+            }
+            Self::Isotropic { .. } => 1. / (4. * PI),
+            Self::Dielectric { .. } | Self::DiffuseLight { .. } => 0.,
+        }
+    }
+
+    pub fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
         match self {
             Self::Lambertian { albedo} => {
-                let mut scatter_direction = rec.normal + rng_unit_vec();
+                let mut scatter_direction = rec.normal + square_to_sphere(sampling::get_2d());
                 if vector_near_zero(&scatter_direction) {
                     scatter_direction = rec.normal
                 }
-                Some((
-                    Ray::with_time(rec.point, scatter_direction, *ray_in.time()),
-                    albedo.value(rec.u, rec.v, rec.point),
-                ))
+                let pdf = self.pdf(ray_in, rec, &scatter_direction);
+                Some(Scatter {
+                    ray: Ray::with_time(rec.point, scatter_direction, *ray_in.time()),
+                    attenuation: albedo.value(rec.u, rec.v, rec.point),
+                    pdf: Some(pdf),
+                })
             }
             Self::Metal { albedo, fuzz } => {
                 let reflected = reflect(&ray_in.direction().normalize(), &rec.normal);
                 let scattered = Ray::with_time(
                     rec.point,
-                    reflected + *fuzz * rng_unit_vec(),
+                    reflected + *fuzz * square_to_sphere(sampling::get_2d()),
                     *ray_in.time(),
                 );
                 if scattered.direction().dot(&rec.normal) > 0. {
-                    Some((scattered, *albedo))
+                    let pdf = (*fuzz > 0.).then(|| self.pdf(ray_in, rec, scattered.direction()));
+                    Some(Scatter {
+                        ray: scattered,
+                        attenuation: *albedo,
+                        pdf,
+                    })
                 } else {
                     None
                 }
@@ -57,25 +131,31 @@ impl Material {
                 let sin_theta = (1. - cos_theta * cos_theta).sqrt();
 
                 let direction = if refract_ratio * sin_theta > 1.
-                    || reflectance(cos_theta, refract_ratio) > random()
+                    || reflectance(cos_theta, refract_ratio) > sampling::get_1d()
                 {
                     reflect(&unit_direction, &rec.normal)
                 } else {
                     refract(&unit_direction, &rec.normal, refract_ratio)
                 };
 
-                Some((
-                    Ray::with_time(rec.point, direction, *ray_in.time()),
-                    Vector3::new(1., 1., 1.),
-                ))
+                Some(Scatter {
+                    ray: Ray::with_time(rec.point, direction, *ray_in.time()),
+                    attenuation: Vector3::new(1., 1., 1.),
+                    pdf: None,
+                })
             }
             Self::DiffuseLight { .. } => {
                 None
             }
             Self::Isotropic { albedo } => {
-                let scattered = Ray::with_time(rec.point, rng_unit_vec(), *ray_in.time());
+                let direction = square_to_sphere(sampling::get_2d());
+                let scattered = Ray::with_time(rec.point, direction, *ray_in.time());
                 let attenuation = albedo.value(rec.u, rec.v, rec.point);
-                Some((scattered, attenuation))
+                Some(Scatter {
+                    ray: scattered,
+                    attenuation,
+                    pdf: Some(1. / (4. * PI)),
+                })
             }
         }
     }
@@ -85,6 +165,28 @@ impl Material {
 //     fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Ray, Vector3<f64>)>;
 // }
 
+/// Returns the density over solid angle of the unit `direction` when a fuzzy metal picks it by
+/// adding a point uniformly distributed over a sphere of radius `fuzz` to the unit `reflected`.
+/// Each point where the ray along `direction` crosses that sphere adds its area density turned
+/// into solid angle.
+fn fuzzed_pdf(reflected: &Vector3<f64>, fuzz: f64, direction: &Vector3<f64>) -> f64 {
+    let b = direction.dot(reflected);
+    let discriminant = b * b - (1. - fuzz * fuzz);
+    if discriminant < 0. {
+        return 0.;
+    }
+    let sqrtd = discriminant.sqrt();
+
+    [b - sqrtd, b + sqrtd]
+        .into_iter()
+        .filter(|&t| t > 0.)
+        .map(|t| {
+            let cosine = (t - b).abs() / fuzz;
+            t * t / (4. * PI * fuzz * fuzz * cosine)
+        })
+        .sum()
+}
+
 fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
     let mut r0 = (1. - ref_idx) / (1. + ref_idx);
     r0 = r0 * r0;
@@ -100,7 +202,7 @@ pub fn vector_near_zero(vec: &Vector3<f64>) -> bool {
 }
 
 pub fn refract(uv: &Vector3<f64>, n: &Vector3<f64>, etai_over_etat: f64) -> Vector3<f64> {
-    let cos_theta = f64::min((-uv).dot(&n), 1.);
+    let cos_theta = f64::min((-uv).dot(n), 1.);
     let r_out_perp = etai_over_etat * (uv + cos_theta * n);
     let r_out_parallel = -(1. - r_out_perp.norm_squared()).abs().sqrt() * n;
     r_out_perp + r_out_parallel
@@ -1,6 +1,6 @@
 mod textures;
 mod material;
 
-pub use material::Material;
+pub use material::{Material, Scatter};
 pub use textures::{Texture, Checker, ImageTexture, NoiseTexture, Perlin, SolidColour};
 
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{any::Any, sync::Arc};
 
 use nalgebra::{Point3, Vector3};
 
@@ -24,6 +24,21 @@ impl Checker {
             odd: Arc::new(SolidColour::new(odd)),
         }
     }
+
+    /// Returns the size of each square.
+    pub fn scale(&self) -> f64 {
+        1. / self.inv_scale
+    }
+
+    /// Returns the texture of the even squares.
+    pub fn even(&self) -> &Arc<dyn Texture> {
+        &self.even
+    }
+
+    /// Returns the texture of the odd squares.
+    pub fn odd(&self) -> &Arc<dyn Texture> {
+        &self.odd
+    }
 }
 
 impl Texture for Checker {
@@ -38,4 +53,8 @@ impl Texture for Checker {
             self.odd.value(u, v, point)
         }
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
@@ -1,3 +1,5 @@
+use std::{any::Any, path::{Path, PathBuf}};
+
 use image::{ImageResult, DynamicImage, GenericImageView};
 use nalgebra::{vector, Point3, Vector3};
 
@@ -9,12 +11,21 @@ use super::Texture;
 
 pub struct ImageTexture {
     image: DynamicImage,
+    path: PathBuf,
 }
 
 impl ImageTexture {
     pub fn new(filename: &str) -> ImageResult<ImageTexture> {
         let image = image::io::Reader::open(filename)?.decode()?;
-        Ok(ImageTexture { image })
+        Ok(ImageTexture {
+            image,
+            path: filename.into(),
+        })
+    }
+
+    /// Returns the path the image was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 }
 
@@ -36,5 +47,8 @@ impl Texture for ImageTexture {
             colour_scale * pixel[2] as f64
         ]
     }
-}
 
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -1,3 +1,5 @@
+use std::any::Any;
+
 use nalgebra::vector;
 
 use super::{Perlin, Texture};
@@ -5,15 +7,27 @@ use super::{Perlin, Texture};
 pub struct NoiseTexture {
     noise: Perlin,
     scale: f64,
+    seed: u64,
 }
 
 impl NoiseTexture {
-    pub fn new(scale: f64) -> NoiseTexture {
+    pub fn new(scale: f64, seed: u64) -> NoiseTexture {
         NoiseTexture {
-            noise: Perlin::new(256),
+            noise: Perlin::new(256, seed),
             scale,
+            seed,
         }
     }
+
+    /// Returns how quickly the noise changes over space.
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// Returns the seed the noise was generated from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
 }
 
 impl Texture for NoiseTexture {
@@ -21,4 +35,8 @@ impl Texture for NoiseTexture {
     fn value(&self, u: f64, v: f64, point: nalgebra::Point3<f64>) -> nalgebra::Vector3<f64> {
         vector![1., 1., 1.] * 0.5 * (1. + f64::sin(self.scale * point.z + 10. * self.noise.turb(point, 7)))
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
@@ -1,8 +1,5 @@
 use nalgebra::{vector, Point3, Vector3};
-use rand::Rng;
-
-use crate::utility::random::rng_vec_bound;
-
+use rand::{rngs::SmallRng, Rng, SeedableRng};
 
 pub struct Perlin {
     ranvec: Vec<Vector3<f64>>,
@@ -12,12 +9,17 @@ pub struct Perlin {
 }
 
 impl Perlin {
-    pub fn new(point_count: usize) -> Perlin {
-        let ranvec = (0..point_count).map(|_| rng_vec_bound(-1., 1.)).collect();
-
-        let perm_x = perlin_generate_perm(point_count);
-        let perm_y = perlin_generate_perm(point_count);
-        let perm_z = perlin_generate_perm(point_count);
+    /// Creates [`Perlin`] noise with `point_count` random gradients. The same `seed` always
+    /// gives the same noise.
+    pub fn new(point_count: usize, seed: u64) -> Perlin {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let ranvec = (0..point_count)
+            .map(|_| Vector3::from_fn(|_, _| rng.gen_range((-1.)..=1.)))
+            .collect();
+
+        let perm_x = perlin_generate_perm(point_count, &mut rng);
+        let perm_y = perlin_generate_perm(point_count, &mut rng);
+        let perm_z = perlin_generate_perm(point_count, &mut rng);
 
         Perlin {
             ranvec,
@@ -41,6 +43,7 @@ impl Perlin {
         accum.abs()
     }
 
+    #[allow(clippy::needless_range_loop)]
     pub fn noise(&self, point: Point3<f64>) -> f64 {
         let u = point.x - point.x.floor();
         let v = point.y - point.y.floor();
@@ -66,6 +69,7 @@ impl Perlin {
     }
 }
 
+#[allow(clippy::needless_range_loop)]
 fn trilinear_interp(c: [[[Vector3<f64>; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
     let uu = u * u * (3. - 2. * u);
     let vv = v * v * (3. - 2. * v);
@@ -86,10 +90,10 @@ fn trilinear_interp(c: [[[Vector3<f64>; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f
     accum
 }
 
-fn perlin_generate_perm(point_count: usize) -> Vec<i32> {
+fn perlin_generate_perm(point_count: usize, rng: &mut SmallRng) -> Vec<i32> {
     let mut p: Vec<i32> = (0..point_count).map(|i| i as i32).collect();
     for i in (1..point_count).rev() {
-        let target: usize = rand::thread_rng().gen_range(0..i);
+        let target: usize = rng.gen_range(0..i);
         p.swap(i, target)
     }
     p
@@ -1,3 +1,5 @@
+use std::any::Any;
+
 use nalgebra::{vector, Point3, Vector3};
 
 use super::Texture;
@@ -15,6 +17,11 @@ impl SolidColour {
             colour: vector![r, g, b],
         }
     }
+
+    /// Returns the colour of this [`SolidColour`].
+    pub fn colour(&self) -> &Vector3<f64> {
+        &self.colour
+    }
 }
 
 impl Texture for SolidColour {
@@ -22,4 +29,8 @@ impl Texture for SolidColour {
     fn value(&self, u: f64, v: f64, point: Point3<f64>) -> Vector3<f64> {
         self.colour
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
@@ -1,8 +1,13 @@
+use std::any::Any;
+
 use nalgebra::{Point3, Vector3};
 
 
 pub trait Texture: Sync + Send {
     fn value(&self, u: f64, v: f64, point: Point3<f64>) -> Vector3<f64>;
+    /// Returns this texture as [`Any`] so that its concrete type can be recovered, which is how
+    /// scenes are exported.
+    fn as_any(&self) -> &dyn Any;
 }
 
 
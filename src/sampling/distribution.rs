@@ -0,0 +1,111 @@
+/// A piecewise constant density over [0, 1), made of equal width steps in proportion to the
+/// values it was built from. Used to importance sample tabulated functions such as the
+/// brightness of an environment map.
+#[derive(Clone)]
+pub struct Distribution1d {
+    func: Vec<f64>,
+    /// The cumulative distribution at the start of each step, ending with 1.
+    cdf: Vec<f64>,
+    /// The integral of the function over [0, 1).
+    integral: f64,
+}
+
+impl Distribution1d {
+    /// Creates a distribution in proportion to `func`, which must not be empty or negative. A
+    /// function that is zero everywhere gives a uniform distribution.
+    pub fn new(func: Vec<f64>) -> Distribution1d {
+        let n = func.len() as f64;
+        let mut cdf = vec![0.];
+        for value in &func {
+            cdf.push(cdf.last().unwrap() + value / n);
+        }
+        let integral = *cdf.last().unwrap();
+        if integral > 0. {
+            cdf.iter_mut().for_each(|c| *c /= integral);
+        } else {
+            cdf.iter_mut()
+                .enumerate()
+                .for_each(|(i, c)| *c = i as f64 / n);
+        }
+
+        Distribution1d {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    /// Returns the integral of the function over [0, 1).
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Maps a number in [0, 1) to a point in [0, 1), returning it with its density and the
+    /// step it lies in.
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        let i = (self.cdf.partition_point(|&c| c <= u) - 1).min(self.func.len() - 1);
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let t = if width > 0. {
+            (u - self.cdf[i]) / width
+        } else {
+            0.5
+        };
+
+        let x = (i as f64 + t) / self.func.len() as f64;
+        (x, self.step_pdf(i), i)
+    }
+
+    /// Returns the density of the point `x` in [0, 1).
+    pub fn pdf(&self, x: f64) -> f64 {
+        let i = ((x * self.func.len() as f64) as usize).min(self.func.len() - 1);
+        self.step_pdf(i)
+    }
+
+    fn step_pdf(&self, i: usize) -> f64 {
+        if self.integral > 0. {
+            self.func[i] / self.integral
+        } else {
+            1.
+        }
+    }
+}
+
+/// A piecewise constant density over the unit square, made of a grid of cells in proportion to
+/// the values it was built from. A row is picked first and then a cell within it.
+#[derive(Clone)]
+pub struct Distribution2d {
+    rows: Vec<Distribution1d>,
+    marginal: Distribution1d,
+}
+
+impl Distribution2d {
+    /// Creates a distribution in proportion to `func`, which holds `width` values for each row
+    /// from the top.
+    pub fn new(func: &[f64], width: usize) -> Distribution2d {
+        let rows: Vec<Distribution1d> = func
+            .chunks(width)
+            .map(|row| Distribution1d::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1d::new(rows.iter().map(|row| row.integral()).collect());
+        Distribution2d { rows, marginal }
+    }
+
+    /// Returns the integral of the function over the unit square.
+    pub fn integral(&self) -> f64 {
+        self.marginal.integral()
+    }
+
+    /// Maps a point in the unit square to another in proportion to the function, returning it
+    /// as `[x, y]` with `y` down from the top, along with its density.
+    pub fn sample(&self, [u1, u2]: [f64; 2]) -> ([f64; 2], f64) {
+        let (y, pdf_y, row) = self.marginal.sample(u2);
+        let (x, pdf_x, _) = self.rows[row].sample(u1);
+        ([x, y], pdf_x * pdf_y)
+    }
+
+    /// Returns the density of the point `[x, y]`.
+    pub fn pdf(&self, [x, y]: [f64; 2]) -> f64 {
+        let row = ((y * self.rows.len() as f64) as usize).min(self.rows.len() - 1);
+        self.marginal.pdf(y) * self.rows[row].pdf(x)
+    }
+}
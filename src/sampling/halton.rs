@@ -0,0 +1,70 @@
+use crate::utility::random::{hash, random};
+
+use super::Sampler;
+
+/// The bases used for each dimension. Dimensions past the end of this list are drawn at random,
+/// since the Halton sequence in large bases is badly correlated for small numbers of samples.
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+/// Uses the Halton sequence, where dimension `d` is the radical inverse of the sample number in
+/// the `d`th prime. Every pixel would otherwise see the same points, so each dimension of each
+/// pixel is shifted by a random offset, wrapping around at 1.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: u32,
+    index: u32,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, pixel: u32, index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        let Some(&base) = PRIMES.get(dimension) else {
+            return random();
+        };
+
+        let key = hash(&[self.seed, self.pixel as u64, dimension as u64]);
+        let offset = (key >> 11) as f64 / (1u64 << 53) as f64;
+        (radical_inverse(base, self.index) + offset).fract()
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        [self.get_1d(), self.get_1d()]
+    }
+}
+
+/// Mirrors the digits of `index` written in `base` about the decimal point.
+fn radical_inverse(base: u32, mut index: u32) -> f64 {
+    let inv_base = 1. / base as f64;
+    let mut inv_base_n = 1.;
+    let mut reversed = 0u64;
+    while index > 0 {
+        reversed = reversed * base as u64 + (index % base) as u64;
+        index /= base;
+        inv_base_n *= inv_base;
+    }
+    (reversed as f64 * inv_base_n).min(1. - f64::EPSILON / 2.)
+}
@@ -0,0 +1,19 @@
+use crate::utility::random::random;
+
+use super::Sampler;
+
+/// Draws every dimension independently from the thread's random number generator, which the
+/// camera seeds for each sample.
+pub struct IndependentSampler;
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, _pixel: u32, _index: u32) {}
+
+    fn get_1d(&mut self) -> f64 {
+        random()
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        [random(), random()]
+    }
+}
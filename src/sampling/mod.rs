@@ -0,0 +1,15 @@
+mod distribution;
+mod halton;
+mod independent;
+mod sampler;
+mod sobol;
+mod stratified;
+mod warp;
+
+pub use distribution::{Distribution1d, Distribution2d};
+pub use halton::HaltonSampler;
+pub use independent::IndependentSampler;
+pub use sampler::{get_1d, get_2d, start_sample, Sampler, SamplerKind};
+pub use sobol::SobolSampler;
+pub use stratified::StratifiedSampler;
+pub use warp::{square_to_cone, square_to_disk, square_to_sphere, to_world};
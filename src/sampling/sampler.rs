@@ -0,0 +1,157 @@
+use std::cell::RefCell;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::utility::random::random;
+
+use super::{HaltonSampler, IndependentSampler, SobolSampler, StratifiedSampler};
+
+/// A source of sample values in [0, 1). Each sample of a pixel is a point in many dimensions,
+/// and every random decision made while tracing the sample takes the next one or two of them.
+/// Spreading these points out more evenly than independent random numbers makes the pixel
+/// converge in fewer samples.
+pub trait Sampler {
+    /// Moves on to sample number `index` of pixel number `pixel`, starting again from the first
+    /// dimension.
+    fn start_pixel_sample(&mut self, pixel: u32, index: u32);
+
+    /// Returns the next dimension of the current sample.
+    fn get_1d(&mut self) -> f64;
+
+    /// Returns the next two dimensions of the current sample, which are spread out as a pair.
+    fn get_2d(&mut self) -> [f64; 2];
+}
+
+/// The samplers a render can be made with.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplerKind {
+    /// Independent uniform random numbers
+    #[default]
+    Independent,
+    /// Jittered points, one in each cell of a grid with a cell for each sample
+    Stratified,
+    /// The Halton sequence, randomly shifted for each pixel
+    Halton,
+    /// The Sobol sequence with Owen scrambling
+    Sobol,
+}
+
+impl SamplerKind {
+    /// Creates a sampler of this kind for renders taking up to `samples_per_pixel` samples.
+    pub fn create(self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+struct Current {
+    /// The kind, samples per pixel and seed the sampler was created with.
+    settings: (SamplerKind, u32, u64),
+    sampler: Box<dyn Sampler>,
+}
+
+thread_local! {
+    /// The sampler for the sample being traced on this thread. It is thread local so that
+    /// materials and media deep in a path can draw from it without it being passed down.
+    static CURRENT: RefCell<Option<Current>> = const { RefCell::new(None) };
+}
+
+/// Starts sample number `index` of pixel number `pixel` on this thread, drawing from a sampler
+/// of the given kind. The sampler is only recreated when its settings change.
+pub fn start_sample(kind: SamplerKind, samples_per_pixel: u32, seed: u64, pixel: u32, index: u32) {
+    CURRENT.with(|current| {
+        let mut current = current.borrow_mut();
+        let settings = (kind, samples_per_pixel, seed);
+        let current = match &mut *current {
+            Some(current) if current.settings == settings => current,
+            slot => slot.insert(Current {
+                settings,
+                sampler: kind.create(samples_per_pixel, seed),
+            }),
+        };
+        current.sampler.start_pixel_sample(pixel, index);
+    })
+}
+
+/// Returns the next dimension of the sample being traced on this thread, or a random number if
+/// no sample has been started.
+pub fn get_1d() -> f64 {
+    CURRENT.with(|current| match &mut *current.borrow_mut() {
+        Some(current) => current.sampler.get_1d(),
+        None => random(),
+    })
+}
+
+/// Returns the next two dimensions of the sample being traced on this thread, or two random
+/// numbers if no sample has been started.
+pub fn get_2d() -> [f64; 2] {
+    CURRENT.with(|current| match &mut *current.borrow_mut() {
+        Some(current) => current.sampler.get_2d(),
+        None => [random(), random()],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utility::random;
+
+    const SAMPLES: u32 = 64;
+    const PIXELS: u32 = 256;
+
+    /// Returns the root mean square error, over many pixels, of estimating the integral of `f`
+    /// over the unit square from `SAMPLES` samples of the given kind. Each sample first draws
+    /// `skip` pairs, as a path would for earlier bounces, so later dimensions are tested too.
+    fn rmse(kind: SamplerKind, skip: usize, f: impl Fn([f64; 2]) -> f64, exact: f64) -> f64 {
+        let squared_error: f64 = (0..PIXELS)
+            .map(|pixel| {
+                let sum: f64 = (0..SAMPLES)
+                    .map(|index| {
+                        random::seed_sample(1, pixel, index);
+                        start_sample(kind, SAMPLES, 1, pixel, index);
+                        for _ in 0..skip {
+                            get_2d();
+                        }
+                        f(get_2d())
+                    })
+                    .sum();
+                (sum / SAMPLES as f64 - exact).powi(2)
+            })
+            .sum();
+        (squared_error / PIXELS as f64).sqrt()
+    }
+
+    fn assert_converges_faster(f: impl Fn([f64; 2]) -> f64 + Copy, exact: f64) {
+        // Halton in the large bases of later dimensions is badly correlated at this few samples,
+        // so only the dimensions of the pixel, the lens and the first bounce are compared
+        for skip in [0, 1, 2] {
+            let independent = rmse(SamplerKind::Independent, skip, f, exact);
+            for kind in [SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol] {
+                let error = rmse(kind, skip, f, exact);
+                assert!(
+                    error < independent * 0.7,
+                    "{kind:?} after {skip} pairs: {error} against {independent} for independent"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_integrand_converges_faster_than_independent() {
+        let exact = (std::f64::consts::E - 1.) / 3.;
+        assert_converges_faster(|[x, y]| x.exp() * y * y, exact);
+    }
+
+    #[test]
+    fn discontinuous_integrand_converges_faster_than_independent() {
+        // A quarter of the unit disk, with an edge like an object's silhouette
+        let disk = |[x, y]: [f64; 2]| if x * x + y * y < 1. { 1. } else { 0. };
+        assert_converges_faster(disk, std::f64::consts::FRAC_PI_4);
+    }
+}
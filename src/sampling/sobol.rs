@@ -0,0 +1,91 @@
+use crate::utility::random::hash;
+
+use super::Sampler;
+
+/// Uses the first two dimensions of the Sobol sequence for every pair of dimensions, with Owen
+/// scrambling so the pairs are independent of each other and of other pixels. The order of the
+/// points is shuffled per pair too, which keeps their even spread for any number of samples
+/// taken so far. This is the hash-based approach from Burley, "Practical Hash-based Owen
+/// Scrambling".
+pub struct SobolSampler {
+    seed: u64,
+    pixel: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> SobolSampler {
+        SobolSampler {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Returns the seeds for shuffling and scrambling the next dimensions.
+    fn next_seeds(&mut self) -> [u32; 3] {
+        let key = hash(&[self.seed, self.pixel as u64, self.dimension as u64]);
+        self.dimension += 1;
+        [key as u32, (key >> 32) as u32, hash(&[key]) as u32]
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, pixel: u32, index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let [shuffle, scramble, _] = self.next_seeds();
+        let index = nested_uniform_scramble(self.index, shuffle);
+        to_unit(nested_uniform_scramble(index.reverse_bits(), scramble))
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        let [shuffle, scramble_x, scramble_y] = self.next_seeds();
+        let index = nested_uniform_scramble(self.index, shuffle);
+        [
+            to_unit(nested_uniform_scramble(index.reverse_bits(), scramble_x)),
+            to_unit(nested_uniform_scramble(sobol_second(index), scramble_y)),
+        ]
+    }
+}
+
+/// The second dimension of the Sobol sequence, as a fixed point fraction.
+fn sobol_second(mut index: u32) -> u32 {
+    let mut result = 0;
+    let mut direction = 1 << 31;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+/// Owen scrambles a fixed point fraction, randomly flipping each digit based on the digits
+/// before it.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/// A hash in which each bit only depends on the bits below it, which is what makes the scramble
+/// nested.
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn to_unit(x: u32) -> f64 {
+    x as f64 / (1u64 << 32) as f64
+}
@@ -0,0 +1,100 @@
+use crate::utility::random::{hash, random};
+
+use super::Sampler;
+
+/// Divides each dimension into as many strata as there are samples per pixel and places each
+/// sample at a random point in its own stratum. Pairs of dimensions are stratified together on
+/// a grid. Which stratum a sample gets is shuffled separately for every pixel and dimension, so
+/// the dimensions are not correlated with each other.
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    pixel: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> StratifiedSampler {
+        StratifiedSampler {
+            samples_per_pixel: samples_per_pixel.max(1),
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Returns the stratum of the current sample out of `count`. Once a pixel has had more
+    /// samples than strata, a fresh shuffle is used for the next round.
+    fn stratum(&mut self, count: u32) -> u32 {
+        let round = self.index / count;
+        let key = hash(&[
+            self.seed,
+            self.pixel as u64,
+            self.dimension as u64,
+            round as u64,
+        ]);
+        self.dimension += 1;
+        permutation_element(self.index % count, count, key as u32)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, pixel: u32, index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let count = self.samples_per_pixel;
+        let stratum = self.stratum(count);
+        (stratum as f64 + random::<f64>()) / count as f64
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        let nx = (self.samples_per_pixel as f64).sqrt() as u32;
+        let ny = self.samples_per_pixel / nx;
+        let stratum = self.stratum(nx * ny);
+        [
+            ((stratum % nx) as f64 + random::<f64>()) / nx as f64,
+            ((stratum / nx) as f64 + random::<f64>()) / ny as f64,
+        ]
+    }
+}
+
+/// Returns element `i` of a random permutation of `0..l` chosen by `p`, without building the
+/// permutation. From Kensler, "Correlated Multi-Jittered Sampling".
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    i.wrapping_add(p) % l
+}
@@ -0,0 +1,48 @@
+use std::f64::consts::{FRAC_PI_4, PI};
+
+use nalgebra::{vector, Vector3};
+
+/// Maps a point in the unit square to a direction uniformly distributed over the unit sphere.
+pub fn square_to_sphere([u1, u2]: [f64; 2]) -> Vector3<f64> {
+    let z = 1. - 2. * u1;
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * PI * u2;
+    vector![r * phi.cos(), r * phi.sin(), z]
+}
+
+/// Maps a point in the unit square to a point uniformly distributed in the unit disk in the xy
+/// plane. Uses Shirley and Chiu's concentric mapping, which keeps nearby points close together.
+pub fn square_to_disk([u1, u2]: [f64; 2]) -> Vector3<f64> {
+    let (a, b) = (2. * u1 - 1., 2. * u2 - 1.);
+    if a == 0. && b == 0. {
+        return Vector3::zeros();
+    }
+
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, FRAC_PI_4 * (b / a))
+    } else {
+        (b, 2. * FRAC_PI_4 - FRAC_PI_4 * (a / b))
+    };
+    vector![r * theta.cos(), r * theta.sin(), 0.]
+}
+
+/// Maps a point in the unit square to a direction uniformly distributed over the cone around
+/// the z axis whose half angle has cosine `cos_theta_max`.
+pub fn square_to_cone([u1, u2]: [f64; 2], cos_theta_max: f64) -> Vector3<f64> {
+    let z = 1. - u1 * (1. - cos_theta_max);
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * PI * u2;
+    vector![r * phi.cos(), r * phi.sin(), z]
+}
+
+/// Returns a direction given in a frame whose z axis is the unit vector `n` in world space.
+pub fn to_world(local: &Vector3<f64>, n: &Vector3<f64>) -> Vector3<f64> {
+    let helper = if n.x.abs() > 0.9 {
+        vector![0., 1., 0.]
+    } else {
+        vector![1., 0., 0.]
+    };
+    let tangent = n.cross(&helper).normalize();
+    let bitangent = n.cross(&tangent);
+    local.x * tangent + local.y * bitangent + local.z * n
+}
@@ -0,0 +1,375 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use toml::Spanned;
+
+use crate::{
+    animation::{Curve, Interpolation},
+    core::{Camera, CameraBuilder, Hittable, HittableList},
+    lights::{Environment, EnvironmentMap, Sky},
+    materials::{Checker, ImageTexture, Material, NoiseTexture, SolidColour, Texture},
+    shapes::{BvhNode, Quad, Sphere},
+    utility::random,
+    wrappers::{ConstantMedium, RotateY, Translate},
+};
+
+use super::format::{EnvironmentDesc, MaterialDesc, ObjectDesc, SceneDesc, TextureDesc, TextureRef};
+
+/// An error from writing a scene out as a scene file.
+#[derive(Debug)]
+pub enum ExportError {
+    /// The scene holds an object the scene format has no way of describing.
+    UnsupportedObject,
+    /// The scene holds a texture the scene format has no way of describing.
+    UnsupportedTexture,
+    /// The scene is lit by an environment the scene format has no way of describing.
+    UnsupportedEnvironment,
+    Serialize(toml::ser::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::UnsupportedObject => write!(f, "scene holds an unsupported object"),
+            ExportError::UnsupportedTexture => write!(f, "scene holds an unsupported texture"),
+            ExportError::UnsupportedEnvironment => {
+                write!(f, "scene is lit by an unsupported environment")
+            }
+            ExportError::Serialize(error) => write!(f, "{error}"),
+            ExportError::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+/// Describes a scene built in Rust in the scene file format. Textures that are shared between
+/// materials stay shared, and identical materials are merged into one.
+pub fn describe_scene(world: &HittableList, camera: &Camera) -> Result<SceneDesc, ExportError> {
+    let mut exporter = Exporter {
+        texture_names: vec![],
+        desc: SceneDesc {
+            camera: Spanned::new(0..0, camera.to_builder()),
+            ..Default::default()
+        },
+    };
+
+    if let Some(environment) = camera.environment() {
+        let desc = describe_environment(environment.as_ref())?;
+        exporter.desc.environment = Some(Spanned::new(0..0, desc));
+    }
+
+    for object in &world.objects {
+        let object = exporter.object(object.as_ref())?;
+        exporter.desc.objects.push(Spanned::new(0..0, object));
+    }
+
+    Ok(exporter.desc)
+}
+
+/// Returns a fingerprint of everything in a scene but the camera, which a render carried on
+/// from a checkpoint is checked against. Images are only referred to by their paths in the
+/// scene, so the files they were read from are taken in too, in case one has been replaced.
+pub fn scene_fingerprint(world: &HittableList, camera: &Camera) -> Result<u64, ExportError> {
+    let desc = SceneDesc {
+        camera: Spanned::new(0..0, CameraBuilder::default()),
+        ..describe_scene(world, camera)?
+    };
+    let mut bytes = toml::to_string(&desc)
+        .map_err(ExportError::Serialize)?
+        .into_bytes();
+
+    let environment = desc.environment.iter().map(|e| e.get_ref());
+    let maps = environment.filter_map(|environment| match environment {
+        EnvironmentDesc::Map { path, .. } => Some(path),
+        EnvironmentDesc::Sky { .. } => None,
+    });
+    let textures = desc.textures.values().filter_map(|texture| match texture.get_ref() {
+        TextureDesc::Image { path } => Some(path),
+        _ => None,
+    });
+    for path in maps.chain(textures) {
+        bytes.extend(fs::read(path).map_err(ExportError::Io)?);
+    }
+    Ok(random::hash_bytes(&bytes))
+}
+
+/// Writes a scene built in Rust as the text of a scene file, which can be read back with
+/// [`super::parse_scene`].
+pub fn export_scene(world: &HittableList, camera: &Camera) -> Result<String, ExportError> {
+    let desc = describe_scene(world, camera)?;
+    toml::to_string(&desc).map_err(ExportError::Serialize)
+}
+
+/// Writes a scene built in Rust to a scene file, which can be read back with
+/// [`super::load_scene`]. Image paths are rewritten to be relative to the scene file where
+/// possible, since that is how they are read.
+pub fn save_scene<P: AsRef<Path>>(
+    path: P,
+    world: &HittableList,
+    camera: &Camera,
+) -> Result<(), ExportError> {
+    let path = path.as_ref();
+    let mut desc = describe_scene(world, camera)?;
+
+    let base_dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let base_dir = std::path::absolute(base_dir).map_err(ExportError::Io)?;
+    let relative = |path: &mut PathBuf| -> Result<(), ExportError> {
+        let absolute = std::path::absolute(&path).map_err(ExportError::Io)?;
+        *path = match absolute.strip_prefix(&base_dir) {
+            Ok(relative) => relative.to_owned(),
+            Err(_) => absolute,
+        };
+        Ok(())
+    };
+    for texture in desc.textures.values_mut() {
+        if let TextureDesc::Image { path } = texture.get_mut() {
+            relative(path)?;
+        }
+    }
+    if let Some(environment) = &mut desc.environment {
+        if let EnvironmentDesc::Map { path, .. } = environment.get_mut() {
+            relative(path)?;
+        }
+    }
+
+    let text = toml::to_string(&desc).map_err(ExportError::Serialize)?;
+    fs::write(path, text).map_err(ExportError::Io)
+}
+
+struct Exporter {
+    /// The names given to textures that have already been described, keyed by their address.
+    texture_names: Vec<(*const (), String)>,
+    desc: SceneDesc,
+}
+
+impl Exporter {
+    fn texture(&mut self, texture: &Arc<dyn Texture>) -> Result<TextureRef, ExportError> {
+        let any = texture.as_any();
+        if let Some(solid) = any.downcast_ref::<SolidColour>() {
+            return Ok(TextureRef::Colour(*solid.colour()));
+        }
+
+        let address = Arc::as_ptr(texture) as *const ();
+        if let Some((_, name)) = self.texture_names.iter().find(|(a, _)| *a == address) {
+            return Ok(TextureRef::Named(name.clone()));
+        }
+
+        let (kind, desc) = if let Some(checker) = any.downcast_ref::<Checker>() {
+            let desc = TextureDesc::Checker {
+                scale: checker.scale(),
+                even: self.texture(checker.even())?,
+                odd: self.texture(checker.odd())?,
+            };
+            ("checker", desc)
+        } else if let Some(image) = any.downcast_ref::<ImageTexture>() {
+            let desc = TextureDesc::Image {
+                path: image.path().to_owned(),
+            };
+            ("image", desc)
+        } else if let Some(noise) = any.downcast_ref::<NoiseTexture>() {
+            let desc = TextureDesc::Noise {
+                scale: noise.scale(),
+                seed: noise.seed(),
+            };
+            ("noise", desc)
+        } else {
+            return Err(ExportError::UnsupportedTexture);
+        };
+
+        let name = unique_name(kind, |name| self.desc.textures.contains_key(name));
+        self.texture_names.push((address, name.clone()));
+        self.desc.textures.insert(name.clone(), Spanned::new(0..0, desc));
+        Ok(TextureRef::Named(name))
+    }
+
+    /// Returns the name of the material, declaring it if no identical material has been.
+    fn material(&mut self, material: &Material) -> Result<String, ExportError> {
+        let (kind, desc) = match material {
+            Material::Lambertian { albedo } => (
+                "lambertian",
+                MaterialDesc::Lambertian {
+                    albedo: self.texture(albedo)?,
+                },
+            ),
+            Material::Metal { albedo, fuzz } => (
+                "metal",
+                MaterialDesc::Metal {
+                    albedo: *albedo,
+                    fuzz: *fuzz,
+                },
+            ),
+            Material::Dielectric { ir } => ("dielectric", MaterialDesc::Dielectric { ir: *ir }),
+            Material::DiffuseLight { emit } => (
+                "diffuse_light",
+                MaterialDesc::DiffuseLight {
+                    emit: self.texture(emit)?,
+                },
+            ),
+            Material::Isotropic { albedo } => (
+                "isotropic",
+                MaterialDesc::Isotropic {
+                    albedo: self.texture(albedo)?,
+                },
+            ),
+        };
+
+        if let Some((name, _)) = self.desc.materials.iter().find(|(_, m)| *m.get_ref() == desc) {
+            return Ok(name.clone());
+        }
+
+        let name = unique_name(kind, |name| self.desc.materials.contains_key(name));
+        self.desc.materials.insert(name.clone(), Spanned::new(0..0, desc));
+        Ok(name)
+    }
+
+    fn object(&mut self, object: &dyn Hittable) -> Result<ObjectDesc, ExportError> {
+        let any = object.as_any();
+        let desc = if let Some(sphere) = any.downcast_ref::<Sphere>() {
+            // A straight line over the first frame is written the short way, with `center2`
+            let path = sphere.center();
+            let (center, center2) = match (path.interpolation(), path.keys()) {
+                (Interpolation::Linear, [a, b]) if a.time == 0. && b.time == 1. => {
+                    (Curve::constant(a.value), Some(b.value))
+                }
+                _ => (path.clone(), None),
+            };
+            ObjectDesc::Sphere {
+                center,
+                radius: sphere.radius(),
+                material: self.material(sphere.material())?,
+                center2,
+            }
+        } else if let Some(quad) = any.downcast_ref::<Quad>() {
+            ObjectDesc::Quad {
+                q: *quad.q(),
+                u: *quad.u(),
+                v: *quad.v(),
+                material: self.material(quad.material())?,
+            }
+        } else if let Some(list) = any.downcast_ref::<HittableList>() {
+            ObjectDesc::List {
+                objects: self.objects(list.objects.iter())?,
+            }
+        } else if let Some(node) = any.downcast_ref::<BvhNode>() {
+            let mut leaves = vec![];
+            bvh_leaves(node, &mut leaves);
+            ObjectDesc::Bvh {
+                objects: self.objects(leaves.into_iter())?,
+            }
+        } else if let Some(translate) = any.downcast_ref::<Translate>() {
+            ObjectDesc::Translate {
+                offset: translate.offset().clone(),
+                object: Box::new(self.object(translate.object().as_ref())?),
+            }
+        } else if let Some(rotate) = any.downcast_ref::<RotateY>() {
+            ObjectDesc::RotateY {
+                angle: rotate.angle().clone(),
+                object: Box::new(self.object(rotate.object().as_ref())?),
+            }
+        } else if let Some(medium) = any.downcast_ref::<ConstantMedium>() {
+            let Material::Isotropic { albedo } = medium.phase_function() else {
+                return Err(ExportError::UnsupportedObject);
+            };
+            ObjectDesc::ConstantMedium {
+                density: medium.density(),
+                albedo: self.texture(albedo)?,
+                boundary: Box::new(self.object(medium.boundary().as_ref())?),
+            }
+        } else {
+            return Err(ExportError::UnsupportedObject);
+        };
+        Ok(desc)
+    }
+
+    fn objects<'a>(
+        &mut self,
+        objects: impl Iterator<Item = &'a Arc<dyn Hittable>>,
+    ) -> Result<Vec<ObjectDesc>, ExportError> {
+        objects.map(|object| self.object(object.as_ref())).collect()
+    }
+}
+
+fn describe_environment(environment: &dyn Environment) -> Result<EnvironmentDesc, ExportError> {
+    let any = environment.as_any();
+    if let Some(map) = any.downcast_ref::<EnvironmentMap>() {
+        Ok(EnvironmentDesc::Map {
+            path: map.path().to_owned(),
+            rotation: map.rotation(),
+            intensity: map.intensity(),
+        })
+    } else if let Some(sky) = any.downcast_ref::<Sky>() {
+        Ok(EnvironmentDesc::Sky {
+            sun_elevation: sky.sun_elevation(),
+            sun_azimuth: sky.sun_azimuth(),
+            turbidity: sky.turbidity(),
+            intensity: sky.intensity(),
+        })
+    } else {
+        Err(ExportError::UnsupportedEnvironment)
+    }
+}
+
+/// Collects the objects a BVH was built from. Nested nodes are flattened, since a BVH built
+/// from the same objects gives the same image.
+fn bvh_leaves<'a>(node: &'a BvhNode, leaves: &mut Vec<&'a Arc<dyn Hittable>>) {
+    let (left, right) = node.children();
+    for child in [left, right] {
+        if let Some(node) = child.as_any().downcast_ref::<BvhNode>() {
+            bvh_leaves(node, leaves);
+        } else if !leaves.iter().any(|leaf| Arc::ptr_eq(leaf, child)) {
+            leaves.push(child);
+        }
+    }
+}
+
+fn unique_name(kind: &str, taken: impl Fn(&str) -> bool) -> String {
+    (1..)
+        .map(|n| format!("{kind}{n}"))
+        .find(|name| !taken(name))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::RenderCounters,
+        scenes::{by_name, parse_scene},
+    };
+
+    /// Renders a small image of `world` through a BVH, as the renderer does.
+    fn render(world: &HittableList, cam: &Camera) -> Vec<nalgebra::Vector3<f64>> {
+        let nodes = BvhNode::new(&world.objects);
+        let (frame, _) = cam.render_film(&nodes, false, &RenderCounters::new());
+        frame.pixels().to_vec()
+    }
+
+    #[test]
+    fn exported_scenes_render_the_same_when_loaded() {
+        for name in ["cornel_box", "cornel_smoke", "two_perlin_spheres"] {
+            let (world, cam) = by_name(name).unwrap()(3);
+            let cam = cam
+                .to_builder()
+                .image_width(24)
+                .samples_per_pixel(4)
+                .seed(7)
+                .build();
+
+            let text = export_scene(&world, &cam).unwrap();
+            let (loaded_world, loaded_cam) = parse_scene(&text, Path::new("")).unwrap();
+            assert_eq!(text, export_scene(&loaded_world, &loaded_cam).unwrap(), "{name}");
+            assert!(
+                render(&world, &cam) == render(&loaded_world, &loaded_cam),
+                "{name} renders differently once exported and loaded"
+            );
+        }
+    }
+}
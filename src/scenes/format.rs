@@ -0,0 +1,202 @@
+use std::{collections::BTreeMap, fmt, path::PathBuf};
+
+use nalgebra::{Point3, Vector3};
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use toml::Spanned;
+
+use crate::{animation::Curve, core::CameraBuilder};
+
+/// A scene as written in a scene file. Textures and materials are declared once by name and
+/// then referred to by that name from materials and objects.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDesc {
+    #[serde(default = "default_camera")]
+    pub camera: Spanned<CameraBuilder>,
+    /// Lights the scene in place of the camera's background colour.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<Spanned<EnvironmentDesc>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub textures: BTreeMap<String, Spanned<TextureDesc>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub materials: BTreeMap<String, Spanned<MaterialDesc>>,
+    #[serde(default)]
+    pub objects: Vec<Spanned<ObjectDesc>>,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum EnvironmentDesc {
+    /// An equirectangular image, turned `rotation` degrees about the y axis and with its
+    /// radiance scaled by `intensity`.
+    Map {
+        path: PathBuf,
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "one")]
+        intensity: f64,
+    },
+    /// A clear daylight sky with the sun in it, see [`crate::lights::Sky`]. Angles are in
+    /// degrees.
+    Sky {
+        #[serde(default = "default_sun_elevation")]
+        sun_elevation: f64,
+        #[serde(default)]
+        sun_azimuth: f64,
+        #[serde(default = "default_turbidity")]
+        turbidity: f64,
+        #[serde(default = "one")]
+        intensity: f64,
+    },
+}
+
+impl Default for SceneDesc {
+    fn default() -> Self {
+        SceneDesc {
+            camera: default_camera(),
+            environment: None,
+            textures: BTreeMap::new(),
+            materials: BTreeMap::new(),
+            objects: vec![],
+        }
+    }
+}
+
+fn default_camera() -> Spanned<CameraBuilder> {
+    Spanned::new(0..0, CameraBuilder::default())
+}
+
+fn one() -> f64 {
+    1.
+}
+
+fn default_sun_elevation() -> f64 {
+    45.
+}
+
+fn default_turbidity() -> f64 {
+    3.
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TextureDesc {
+    Solid {
+        colour: Vector3<f64>,
+    },
+    Checker {
+        scale: f64,
+        even: TextureRef,
+        odd: TextureRef,
+    },
+    Image {
+        path: PathBuf,
+    },
+    /// Perlin noise. The same `seed` always gives the same pattern.
+    Noise {
+        scale: f64,
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum MaterialDesc {
+    Lambertian { albedo: TextureRef },
+    Metal { albedo: Vector3<f64>, fuzz: f64 },
+    Dielectric { ir: f64 },
+    DiffuseLight { emit: TextureRef },
+    Isotropic { albedo: TextureRef },
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ObjectDesc {
+    /// A sphere, whose centre can follow a curve. `center2` is a shorter way of writing a
+    /// centre that moves in a straight line from `center` at time 0 to `center2` at time 1.
+    Sphere {
+        center: Curve<Point3<f64>>,
+        radius: f64,
+        material: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        center2: Option<Point3<f64>>,
+    },
+    Quad {
+        q: Point3<f64>,
+        u: Vector3<f64>,
+        v: Vector3<f64>,
+        material: String,
+    },
+    /// An axis aligned box with opposite corners `a` and `b`, see [`crate::shapes::make_box`].
+    Box {
+        a: Point3<f64>,
+        b: Point3<f64>,
+        material: String,
+    },
+    Translate {
+        offset: Curve<Vector3<f64>>,
+        object: Box<ObjectDesc>,
+    },
+    /// A rotation about the y axis by `angle` degrees.
+    RotateY {
+        angle: Curve<f64>,
+        object: Box<ObjectDesc>,
+    },
+    ConstantMedium {
+        density: f64,
+        albedo: TextureRef,
+        boundary: Box<ObjectDesc>,
+    },
+    List {
+        objects: Vec<ObjectDesc>,
+    },
+    Bvh {
+        objects: Vec<ObjectDesc>,
+    },
+}
+
+/// A texture used by a material, either the name of a texture declared in the scene or a solid
+/// colour written inline as `[r, g, b]`.
+#[derive(Clone, PartialEq)]
+pub enum TextureRef {
+    Named(String),
+    Colour(Vector3<f64>),
+}
+
+impl Serialize for TextureRef {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            TextureRef::Named(name) => serializer.serialize_str(name),
+            TextureRef::Colour(colour) => colour.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TextureRef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TextureRefVisitor;
+
+        impl<'de> Visitor<'de> for TextureRefVisitor {
+            type Value = TextureRef;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a texture name or an [r, g, b] colour")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(TextureRef::Named(v.to_owned()))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                let colour = Deserialize::deserialize(de::value::SeqAccessDeserializer::new(seq))?;
+                Ok(TextureRef::Colour(colour))
+            }
+        }
+
+        deserializer.deserialize_any(TextureRefVisitor)
+    }
+}
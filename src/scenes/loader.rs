@@ -0,0 +1,354 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    core::{Camera, Hittable, HittableList},
+    lights::{Environment, EnvironmentMap, Sky},
+    materials::{Checker, ImageTexture, Material, NoiseTexture, SolidColour, Texture},
+    shapes::{make_box, BvhNode, Quad, Sphere},
+    wrappers::{ConstantMedium, RotateY, Translate},
+};
+
+use super::format::{EnvironmentDesc, MaterialDesc, ObjectDesc, SceneDesc, TextureDesc, TextureRef};
+
+/// An error from reading a scene file.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file could not be read.
+    Io { path: PathBuf, error: io::Error },
+    /// The file is not valid TOML or does not match the scene format. The message includes the
+    /// line and the offending key.
+    Parse(toml::de::Error),
+    /// The file is well formed but describes something that cannot be built, such as a
+    /// reference to a material that does not exist.
+    Invalid {
+        line: usize,
+        field: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io { path, error } => write!(f, "failed to read {}: {error}", path.display()),
+            LoadError::Parse(error) => write!(f, "{error}"),
+            LoadError::Invalid {
+                line,
+                field,
+                message,
+            } => write!(f, "line {line}: `{field}`: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Reads a scene file. Relative image paths in the file are resolved against the directory the
+/// file is in.
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<(HittableList, Camera), LoadError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|error| LoadError::Io {
+        path: path.to_owned(),
+        error,
+    })?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    parse_scene(&source, base_dir)
+}
+
+/// Builds a scene from the contents of a scene file. Relative image paths are resolved against
+/// `base_dir`.
+pub fn parse_scene(source: &str, base_dir: &Path) -> Result<(HittableList, Camera), LoadError> {
+    let desc: SceneDesc = toml::from_str(source).map_err(LoadError::Parse)?;
+    let mut loader = Loader {
+        source,
+        base_dir,
+        desc: &desc,
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+    };
+
+    for name in desc.textures.keys() {
+        loader.texture_by_name(name, &mut vec![])?;
+    }
+    for (name, mat) in &desc.materials {
+        let field = format!("materials.{name}");
+        let material = loader.material(mat.get_ref(), mat.span(), &field)?;
+        loader.materials.insert(name.clone(), material);
+    }
+
+    if let Err((name, message)) = desc.camera.get_ref().check() {
+        let field = format!("camera.{name}");
+        return Err(loader.invalid(&desc.camera.span(), &field, message));
+    }
+    let mut camera = desc.camera.get_ref().clone();
+    if let Some(environment) = &desc.environment {
+        camera = camera.environment(loader.environment(environment.get_ref(), environment.span())?);
+    }
+
+    let mut world = HittableList::new();
+    for (i, object) in desc.objects.iter().enumerate() {
+        let field = format!("objects[{i}]");
+        world.add(loader.object(object.get_ref(), object.span(), &field)?);
+    }
+    if world.objects.is_empty() {
+        // There is no span for a missing list, so point at the start of the file
+        let message = "a scene needs at least one object".to_owned();
+        return Err(loader.invalid(&(0..0), "objects", message));
+    }
+
+    Ok((world, camera.build()))
+}
+
+struct Loader<'a> {
+    source: &'a str,
+    base_dir: &'a Path,
+    desc: &'a SceneDesc,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Material>,
+}
+
+impl Loader<'_> {
+    fn invalid(&self, span: &Range<usize>, field: &str, message: String) -> LoadError {
+        let line = self.source[..span.start.min(self.source.len())]
+            .matches('\n')
+            .count()
+            + 1;
+        LoadError::Invalid {
+            line,
+            field: field.to_owned(),
+            message,
+        }
+    }
+
+    /// Builds the texture declared as `name`, building any textures it refers to first.
+    /// `visiting` holds the textures currently being built so that cycles can be reported.
+    fn texture_by_name(
+        &mut self,
+        name: &str,
+        visiting: &mut Vec<String>,
+    ) -> Result<Arc<dyn Texture>, LoadError> {
+        if let Some(texture) = self.textures.get(name) {
+            return Ok(texture.clone());
+        }
+
+        let desc = &self.desc.textures[name];
+        let span = desc.span();
+        let field = format!("textures.{name}");
+
+        if visiting.iter().any(|n| n == name) {
+            let message = format!("texture refers to itself through `{}`", visiting.join("` -> `"));
+            return Err(self.invalid(&span, &field, message));
+        }
+        visiting.push(name.to_owned());
+
+        let texture: Arc<dyn Texture> = match desc.get_ref() {
+            TextureDesc::Solid { colour } => Arc::new(SolidColour::new(*colour)),
+            TextureDesc::Checker { scale, even, odd } => {
+                if *scale == 0. {
+                    let message = "checker scale must not be zero".to_owned();
+                    return Err(self.invalid(&span, &format!("{field}.scale"), message));
+                }
+                let even = self.texture(even, &span, &format!("{field}.even"), visiting)?;
+                let odd = self.texture(odd, &span, &format!("{field}.odd"), visiting)?;
+                Arc::new(Checker::new(*scale, &even, &odd))
+            }
+            TextureDesc::Image { path } => {
+                let path = self.base_dir.join(path);
+                let image = ImageTexture::new(&path.to_string_lossy()).map_err(|e| {
+                    let message = format!("failed to load {}: {e}", path.display());
+                    self.invalid(&span, &format!("{field}.path"), message)
+                })?;
+                Arc::new(image)
+            }
+            TextureDesc::Noise { scale, seed } => Arc::new(NoiseTexture::new(*scale, *seed)),
+        };
+
+        visiting.pop();
+        self.textures.insert(name.to_owned(), texture.clone());
+        Ok(texture)
+    }
+
+    fn texture(
+        &mut self,
+        texture: &TextureRef,
+        span: &Range<usize>,
+        field: &str,
+        visiting: &mut Vec<String>,
+    ) -> Result<Arc<dyn Texture>, LoadError> {
+        match texture {
+            TextureRef::Colour(colour) => Ok(Arc::new(SolidColour::new(*colour))),
+            TextureRef::Named(name) if self.desc.textures.contains_key(name) => {
+                self.texture_by_name(name, visiting)
+            }
+            TextureRef::Named(name) => {
+                Err(self.invalid(span, field, format!("unknown texture `{name}`")))
+            }
+        }
+    }
+
+    fn environment(
+        &self,
+        desc: &EnvironmentDesc,
+        span: Range<usize>,
+    ) -> Result<Arc<dyn Environment>, LoadError> {
+        let environment: Arc<dyn Environment> = match desc {
+            EnvironmentDesc::Map {
+                path,
+                rotation,
+                intensity,
+            } => {
+                let path = self.base_dir.join(path);
+                let map = EnvironmentMap::new(&path, *rotation, *intensity).map_err(|e| {
+                    let message = format!("failed to load {}: {e}", path.display());
+                    self.invalid(&span, "environment.path", message)
+                })?;
+                Arc::new(map)
+            }
+            EnvironmentDesc::Sky {
+                sun_elevation,
+                sun_azimuth,
+                turbidity,
+                intensity,
+            } => {
+                if !(2. ..=10.).contains(turbidity) {
+                    let message = "turbidity must be between 2 and 10".to_owned();
+                    return Err(self.invalid(&span, "environment.turbidity", message));
+                }
+                Arc::new(Sky::new(*sun_elevation, *sun_azimuth, *turbidity, *intensity))
+            }
+        };
+        Ok(environment)
+    }
+
+    fn material(
+        &mut self,
+        desc: &MaterialDesc,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<Material, LoadError> {
+        let material = match desc {
+            MaterialDesc::Lambertian { albedo } => Material::Lambertian {
+                albedo: self.texture(albedo, &span, &format!("{field}.albedo"), &mut vec![])?,
+            },
+            MaterialDesc::Metal { albedo, fuzz } => Material::Metal {
+                albedo: *albedo,
+                fuzz: *fuzz,
+            },
+            MaterialDesc::Dielectric { ir } => {
+                if *ir <= 0. {
+                    let message = "refractive index must be positive".to_owned();
+                    return Err(self.invalid(&span, &format!("{field}.ir"), message));
+                }
+                Material::Dielectric { ir: *ir }
+            }
+            MaterialDesc::DiffuseLight { emit } => Material::DiffuseLight {
+                emit: self.texture(emit, &span, &format!("{field}.emit"), &mut vec![])?,
+            },
+            MaterialDesc::Isotropic { albedo } => Material::Isotropic {
+                albedo: self.texture(albedo, &span, &format!("{field}.albedo"), &mut vec![])?,
+            },
+        };
+        Ok(material)
+    }
+
+    fn material_by_name(
+        &self,
+        name: &str,
+        span: &Range<usize>,
+        field: &str,
+    ) -> Result<&Material, LoadError> {
+        self.materials
+            .get(name)
+            .ok_or_else(|| self.invalid(span, field, format!("unknown material `{name}`")))
+    }
+
+    /// Builds an object. `span` is the span of the top level object it belongs to, since nested
+    /// objects do not carry their own position.
+    fn object(
+        &mut self,
+        desc: &ObjectDesc,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<Box<dyn Hittable>, LoadError> {
+        let object: Box<dyn Hittable> = match desc {
+            ObjectDesc::Sphere {
+                center,
+                radius,
+                material,
+                center2,
+            } => {
+                let mat = self.material_by_name(material, &span, &format!("{field}.material"))?;
+                match center2 {
+                    Some(center2) if center.is_constant() => {
+                        let center = center.at(0.);
+                        Box::new(Sphere::new_moving(center, *center2, *radius, mat))
+                    }
+                    Some(_) => {
+                        let message =
+                            "`center2` cannot be used with a curve for `center`".to_owned();
+                        return Err(self.invalid(&span, &format!("{field}.center2"), message));
+                    }
+                    None => Box::new(Sphere::animated(center.clone(), *radius, mat)),
+                }
+            }
+            ObjectDesc::Quad { q, u, v, material } => {
+                let mat = self.material_by_name(material, &span, &format!("{field}.material"))?;
+                Quad::boxed(*q, *u, *v, mat)
+            }
+            ObjectDesc::Box { a, b, material } => {
+                let mat = self.material_by_name(material, &span, &format!("{field}.material"))?;
+                Box::new(make_box(*a, *b, mat))
+            }
+            ObjectDesc::Translate { offset, object } => {
+                let object = self.object(object, span, &format!("{field}.object"))?;
+                Box::new(Translate::animated(object.into(), offset.clone()))
+            }
+            ObjectDesc::RotateY { angle, object } => {
+                let object = self.object(object, span, &format!("{field}.object"))?;
+                Box::new(RotateY::animated(object.into(), angle.clone()))
+            }
+            ObjectDesc::ConstantMedium {
+                density,
+                albedo,
+                boundary,
+            } => {
+                if *density <= 0. {
+                    let message = "density must be positive".to_owned();
+                    return Err(self.invalid(&span, &format!("{field}.density"), message));
+                }
+                let albedo = self.texture(albedo, &span, &format!("{field}.albedo"), &mut vec![])?;
+                let boundary = self.object(boundary, span, &format!("{field}.boundary"))?;
+                Box::new(ConstantMedium::new(boundary.into(), *density, albedo))
+            }
+            ObjectDesc::List { objects } => Box::new(self.list(objects, span, field)?),
+            ObjectDesc::Bvh { objects } => {
+                let list = self.list(objects, span.clone(), field)?;
+                if list.objects.is_empty() {
+                    let message = "a bvh needs at least one object".to_owned();
+                    return Err(self.invalid(&span, &format!("{field}.objects"), message));
+                }
+                Box::new(BvhNode::new(&list.objects))
+            }
+        };
+        Ok(object)
+    }
+
+    fn list(
+        &mut self,
+        objects: &[ObjectDesc],
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<HittableList, LoadError> {
+        let mut list = HittableList::new();
+        for (i, object) in objects.iter().enumerate() {
+            list.add(self.object(object, span.clone(), &format!("{field}.objects[{i}]"))?);
+        }
+        Ok(list)
+    }
+}
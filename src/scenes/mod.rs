@@ -1,3 +1,9 @@
+#[allow(clippy::module_inception)]
 mod scenes;
+mod export;
+mod format;
 mod loader;
 pub use scenes::*;
+pub use export::{describe_scene, export_scene, save_scene, scene_fingerprint, ExportError};
+pub use format::{EnvironmentDesc, MaterialDesc, ObjectDesc, SceneDesc, TextureDesc, TextureRef};
+pub use loader::{load_scene, parse_scene, LoadError};
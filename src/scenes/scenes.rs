@@ -1,23 +1,47 @@
 use std::sync::Arc;
 
 use nalgebra::{point, vector, Vector3};
-use rand::{random, Rng};
 
 use crate::{
     core::{Camera, HittableList},
     materials::{Checker, ImageTexture, Material, NoiseTexture, SolidColour},
     shapes::{make_box, BvhNode, Quad, Sphere},
-    utility::random::rng_vec_bound,
+    utility::random::{self, random, random_range, rng_vec_bound},
     wrappers::{ConstantMedium, RotateY, Translate},
 };
 
-pub type Scene = fn() -> (HittableList, Camera);
+/// Builds a scene from a seed. Scenes with random parts are the same every time they are built
+/// from the same seed, the rest ignore it.
+pub type Scene = fn(u64) -> (HittableList, Camera);
+
+/// Every built-in scene along with the name used to select it.
+pub const SCENES: &[(&str, Scene)] = &[
+    ("final_scene", |seed| final_scene(800, 1000, 40, seed)),
+    ("cornel_smoke", |_| cornel_smoke()),
+    ("cornel_box", |_| cornel_box()),
+    ("simple_light", simple_light),
+    ("quads", |_| quads()),
+    ("two_perlin_spheres", two_perlin_spheres),
+    ("earth", |_| earth()),
+    ("two_spheres", |_| two_spheres()),
+    ("random_balls", random_balls),
+];
+
+/// Looks up a built-in scene by name.
+pub fn by_name(name: &str) -> Option<Scene> {
+    SCENES
+        .iter()
+        .find(|(scene_name, _)| *scene_name == name)
+        .map(|(_, scene)| *scene)
+}
 
 pub fn final_scene(
     image_width: u32,
     samples_per_pixel: u32,
     max_depth: u32,
+    seed: u64,
 ) -> (HittableList, Camera) {
+    random::seed(seed);
     let cam = Camera::builder()
         .aspect_ratio(1.)
         .image_width(image_width)
@@ -45,7 +69,7 @@ pub fn final_scene(
             let z0 = -1000. + j as f64 * w;
             let y0 = 0.;
             let x1 = x0 + w;
-            let y1 = rand::thread_rng().gen_range(1..101) as f64;
+            let y1 = random_range(1..101) as f64;
             let z1 = z0 + w;
             boxes1.add(Box::new(make_box(
                 point![x0, y0, z0],
@@ -104,7 +128,7 @@ pub fn final_scene(
         albedo: Arc::new(ImageTexture::new("earthmap.jpg").unwrap()),
     };
     world.add(Box::new(Sphere::new(point![400., 200., 400.], 100., &emat)));
-    let pertext = NoiseTexture::new(0.1);
+    let pertext = NoiseTexture::new(0.1, seed);
     world.add(Box::new(Sphere::new(
         point![220., 280., 300.],
         80.,
@@ -299,7 +323,7 @@ pub fn cornel_box() -> (HittableList, Camera) {
     (world, cam)
 }
 
-pub fn simple_light() -> (HittableList, Camera) {
+pub fn simple_light(seed: u64) -> (HittableList, Camera) {
     let cam = Camera::builder()
         .aspect_ratio(16. / 9.)
         .image_width(400)
@@ -315,7 +339,7 @@ pub fn simple_light() -> (HittableList, Camera) {
         .build();
 
     let mut world = HittableList::new();
-    let pertext = Arc::new(NoiseTexture::new(4.));
+    let pertext = Arc::new(NoiseTexture::new(4., seed));
     let mat = Material::Lambertian {
         albedo: pertext.clone(),
     };
@@ -402,7 +426,7 @@ pub fn quads() -> (HittableList, Camera) {
     (world, cam)
 }
 
-pub fn two_perlin_spheres() -> (HittableList, Camera) {
+pub fn two_perlin_spheres(seed: u64) -> (HittableList, Camera) {
     let cam = Camera::builder()
         .aspect_ratio( 16. / 9.)
         .image_width( 400)
@@ -418,7 +442,7 @@ pub fn two_perlin_spheres() -> (HittableList, Camera) {
     .build();
 
     let mut world = HittableList::new();
-    let perlin_texture = Arc::new(NoiseTexture::new(4.));
+    let perlin_texture = Arc::new(NoiseTexture::new(4., seed));
     let perlin_material = Material::Lambertian {
         albedo: perlin_texture,
     };
@@ -502,7 +526,8 @@ pub fn two_spheres() -> (HittableList, Camera) {
 }
 
 /// Generates the scene from the end of the Ray Tracing in One Weekend book
-pub fn random_balls() -> (HittableList, Camera) {
+pub fn random_balls(seed: u64) -> (HittableList, Camera) {
+    random::seed(seed);
     let cam = Camera::builder()
         .aspect_ratio( 16. / 9.)
         .image_width( 400)
@@ -533,7 +558,7 @@ pub fn random_balls() -> (HittableList, Camera) {
             let center = point![
                 a as f64 + 0.9 * random::<f64>(),
                 0.2,
-                b as f64 + 0.9 as f64 * random::<f64>()
+                b as f64 + 0.9 * random::<f64>()
             ];
 
             if (center - point![4., 0.2, 0.]).norm() > 0.9 {
@@ -550,7 +575,7 @@ pub fn random_balls() -> (HittableList, Camera) {
                     world.add(Box::new(Sphere::new_moving(center, center2, 0.2, &mat)));
                 } else if chose_mat < 0.95 {
                     let albedo = rng_vec_bound(0.5, 1.);
-                    let fuzz = rand::thread_rng().gen_range((0.)..0.5);
+                    let fuzz = random_range((0.)..0.5);
                     let mat = Material::Metal { albedo, fuzz };
                     world.add(Box::new(Sphere::new(center, 0.2, &mat)));
                 } else {
@@ -1,9 +1,10 @@
-use std::{cmp::Ordering, sync::Arc};
+use std::{any::Any, cmp::Ordering, sync::Arc};
 
-use rand::Rng;
+use nalgebra::{Point3, Vector3};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
 
 use crate::{
-    core::{HitRecord, Hittable, Ray},
+    core::{Counter, HitRecord, Hittable, HittableList, Ray},
     utility::Interval,
 };
 
@@ -13,34 +14,83 @@ pub struct BvhNode {
     left: Arc<dyn Hittable>,
     right: Arc<dyn Hittable>,
     bbox: Aabb,
+    /// The indices of the children among the objects the tree was built from, for children
+    /// that are objects rather than further nodes. Hits on them are marked with these.
+    left_id: Option<u32>,
+    right_id: Option<u32>,
 }
 
 impl BvhNode {
-    pub fn new(src_objects: &Vec<Arc<dyn Hittable>>) -> BvhNode {
-        let mut objects = src_objects.clone();
-        let axis: usize = rand::thread_rng().gen_range(0..2);
+    /// Builds a tree over `src_objects`. Each node is split along a randomly chosen axis, but
+    /// the choices come from a fixed seed so the same objects always give the same tree. With no
+    /// objects the tree is a single empty node that nothing hits.
+    pub fn new(src_objects: &[Arc<dyn Hittable>]) -> BvhNode {
+        if src_objects.is_empty() {
+            let empty: Arc<dyn Hittable> = Arc::new(HittableList::new());
+            return BvhNode {
+                left: empty.clone(),
+                right: empty,
+                bbox: Aabb::default(),
+                left_id: None,
+                right_id: None,
+            };
+        }
+        let objects: Vec<_> = src_objects
+            .iter()
+            .enumerate()
+            .map(|(id, object)| (id as u32, object.clone()))
+            .collect();
+        BvhNode::build(&objects, &mut SmallRng::seed_from_u64(0))
+    }
+
+    fn build(src_objects: &[(u32, Arc<dyn Hittable>)], rng: &mut SmallRng) -> BvhNode {
+        let mut objects = src_objects.to_vec();
+        let axis: usize = rng.gen_range(0..2);
 
-        let (left, right) = if objects.len() == 1 {
-            (objects[0].clone(), objects[0].clone())
+        let leaf = |(id, object): &(u32, Arc<dyn Hittable>)| (object.clone(), Some(*id));
+        let ((left, left_id), (right, right_id)) = if objects.len() == 1 {
+            (leaf(&objects[0]), leaf(&objects[0]))
         } else if objects.len() == 2 {
-            if box_compare(&objects[0], &objects[1], axis).is_gt() {
-                (objects[0].clone(), objects[1].clone())
+            if box_compare(&objects[0].1, &objects[1].1, axis).is_gt() {
+                (leaf(&objects[0]), leaf(&objects[1]))
             } else {
-                (objects[1].clone(), objects[0].clone())
+                (leaf(&objects[1]), leaf(&objects[0]))
             }
         } else {
-            objects.sort_unstable_by(|a, b| box_compare(a, b, axis).reverse());
+            objects.sort_unstable_by(|a, b| box_compare(&a.1, &b.1, axis).reverse());
             let mid = objects.len() / 2;
-            let left: Arc<dyn Hittable> = Arc::new(BvhNode::new(&objects[0..mid].into()));
-            let right: Arc<dyn Hittable> = Arc::new(BvhNode::new(&objects[mid..].into()));
+            let left: Arc<dyn Hittable> = Arc::new(BvhNode::build(&objects[0..mid], rng));
+            let right: Arc<dyn Hittable> = Arc::new(BvhNode::build(&objects[mid..], rng));
 
-            (left, right)
+            ((left, None), (right, None))
         };
-        let bbox = Aabb::merge(&left.bounding_box(), &right.bounding_box());
-        BvhNode { left, right, bbox }
+        let bbox = Aabb::merge(left.bounding_box(), right.bounding_box());
+        BvhNode {
+            left,
+            right,
+            bbox,
+            left_id,
+            right_id,
+        }
+    }
+
+    /// Returns the two children of this node. Both are the same object when the node was built
+    /// from a single object.
+    pub fn children(&self) -> (&Arc<dyn Hittable>, &Arc<dyn Hittable>) {
+        (&self.left, &self.right)
     }
 }
 
+/// Marks a hit on a child with the child's index, if it has one.
+fn mark(rec: Option<HitRecord<'_>>, id: Option<u32>) -> Option<HitRecord<'_>> {
+    rec.map(|mut rec| {
+        if let Some(id) = id {
+            rec.object_id = id;
+        }
+        rec
+    })
+}
+
 fn box_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>, axis: usize) -> Ordering {
     a.bounding_box()
         .axis(axis)
@@ -49,12 +99,21 @@ fn box_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>, axis: usize) -> Ord
         .unwrap()
 }
 impl Hittable for BvhNode {
-    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        Counter::NodeVisited.increment();
         if !self.bbox.hit(ray, ray_t) {
             return None;
         }
 
-        let hit_left = self.left.hit(ray, ray_t);
+        // Children with an ID are the objects themselves rather than more nodes. A leaf holding
+        // one object has it on both sides, but it is only counted once
+        if self.left_id.is_some() {
+            Counter::PrimitiveTest.increment();
+        }
+        if self.right_id.is_some() && !Arc::ptr_eq(&self.left, &self.right) {
+            Counter::PrimitiveTest.increment();
+        }
+        let hit_left = mark(self.left.hit(ray, ray_t), self.left_id);
         let hit_right = self.right.hit(
             ray,
             Interval::new(
@@ -66,6 +125,7 @@ impl Hittable for BvhNode {
                 },
             ),
         );
+        let hit_right = mark(hit_right, self.right_id);
 
         if hit_right.is_some() {
             hit_right
@@ -77,4 +137,27 @@ impl Hittable for BvhNode {
     fn bounding_box(&self) -> &Aabb {
         &self.bbox
     }
+
+    fn pdf_value(&self, origin: &Point3<f64>, direction: &Vector3<f64>, time: f64) -> f64 {
+        if Arc::ptr_eq(&self.left, &self.right) {
+            return self.left.pdf_value(origin, direction, time);
+        }
+        0.5 * (self.left.pdf_value(origin, direction, time)
+            + self.right.pdf_value(origin, direction, time))
+    }
+
+    fn random(&self, origin: &Point3<f64>, [u1, u2]: [f64; 2], time: f64) -> Vector3<f64> {
+        // Pick a child evenly, then reuse what is left of `u1` to sample it
+        if Arc::ptr_eq(&self.left, &self.right) {
+            self.left.random(origin, [u1, u2], time)
+        } else if u1 < 0.5 {
+            self.left.random(origin, [2. * u1, u2], time)
+        } else {
+            self.right.random(origin, [2. * u1 - 1., u2], time)
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
@@ -1,3 +1,5 @@
+use std::any::Any;
+
 use nalgebra::{point, vector, Point3, Vector3};
 
 use crate::{core::{HitRecord, Hittable, HittableList, Ray}, materials::Material, utility::Interval};
@@ -37,6 +39,26 @@ impl Quad {
             w,
         }
     }
+
+    /// Returns the corner of this [`Quad`].
+    pub fn q(&self) -> &Point3<f64> {
+        &self.q
+    }
+
+    /// Returns the first edge of this [`Quad`].
+    pub fn u(&self) -> &Vector3<f64> {
+        &self.u
+    }
+
+    /// Returns the second edge of this [`Quad`].
+    pub fn v(&self) -> &Vector3<f64> {
+        &self.v
+    }
+
+    /// Returns a reference to the material of this [`Quad`].
+    pub fn material(&self) -> &Material {
+        &self.mat
+    }
 }
 
 impl Hittable for Quad {
@@ -44,7 +66,7 @@ impl Hittable for Quad {
         &self,
         ray: &Ray,
         ray_t: Interval,
-    ) -> Option<HitRecord> {
+    ) -> Option<HitRecord<'_>> {
         let denom = self.normal.dot(ray.direction());
 
         if denom.abs() < 1e-8 {
@@ -65,7 +87,7 @@ impl Hittable for Quad {
         let alpha = self.w.dot(&planar_hitpt_vector.cross(&self.v));
         let beta = self.w.dot(&self.u.cross(&planar_hitpt_vector));
 
-        if alpha < 0. || 1. < alpha || beta < 0. || 1. < beta {
+        if !(0. ..=1.).contains(&alpha) || !(0. ..=1.).contains(&beta) {
             return None;
         }
 
@@ -83,6 +105,28 @@ impl Hittable for Quad {
     fn bounding_box(&self) -> &Aabb {
         &self.bbox
     }
+
+    fn pdf_value(&self, origin: &Point3<f64>, direction: &Vector3<f64>, time: f64) -> f64 {
+        let ray = Ray::with_time(*origin, *direction, time);
+        match self.hit(&ray, Interval::new(0.001, f64::MAX)) {
+            Some(rec) => {
+                // Points are picked uniformly by area, so convert that to solid angle
+                let distance_squared = rec.t * rec.t * direction.norm_squared();
+                let cosine = (direction.dot(&self.normal) / direction.norm()).abs();
+                let area = self.u.cross(&self.v).norm();
+                distance_squared / (cosine * area)
+            }
+            None => 0.,
+        }
+    }
+
+    fn random(&self, origin: &Point3<f64>, [u1, u2]: [f64; 2], _time: f64) -> Vector3<f64> {
+        self.q + u1 * self.u + u2 * self.v - origin
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 pub fn make_box(a: Point3<f64>, b: Point3<f64>, mat: &Material) -> HittableList {
@@ -1,68 +1,95 @@
-use std::f64::consts::PI;
+use std::{any::Any, f64::consts::PI};
 
 use nalgebra::{vector, Point3, Vector3};
 
-use crate::{core::{HitRecord, Hittable, Ray}, materials::Material, utility::Interval};
+use crate::{
+    animation::{Curve, Interpolation, Key},
+    core::{HitRecord, Hittable, Ray},
+    materials::Material,
+    sampling::{square_to_cone, square_to_sphere, to_world},
+    utility::Interval,
+};
 
 use super::Aabb;
 
 
 pub struct Sphere {
-    center1: Point3<f64>,
+    center: Curve<Point3<f64>>,
     radius: f64,
     mat: Material,
-    center_vec: Option<Vector3<f64>>,
     bbox: Aabb,
 }
 
 impl Sphere {
     pub fn new(center: Point3<f64>, radius: f64, mat: &Material) -> Sphere {
-        let rvec = vector![radius, radius, radius];
-        let bbox = Aabb::from_points(center + rvec, center - rvec);
-        Sphere {
-            center1: center,
-            radius,
-            mat: mat.clone(),
-            center_vec: None,
-            bbox,
-        }
+        Sphere::animated(Curve::constant(center), radius, mat)
     }
+    /// Creates a sphere moving in a straight line from `center1` at time 0 to `center2` at
+    /// time 1. Like any [`Curve`], it holds still outside that time rather than carrying on
+    /// along the line, so a shutter open past it sees the sphere stop at one end.
     pub fn new_moving(
         center1: Point3<f64>,
         center2: Point3<f64>,
         radius: f64,
         mat: &Material,
     ) -> Sphere {
+        let keys = vec![
+            Key {
+                time: 0.,
+                value: center1,
+            },
+            Key {
+                time: 1.,
+                value: center2,
+            },
+        ];
+        Sphere::animated(Curve::new(Interpolation::Linear, keys), radius, mat)
+    }
+
+    /// Creates a sphere whose centre follows `center` over time.
+    pub fn animated(center: Curve<Point3<f64>>, radius: f64, mat: &Material) -> Sphere {
         let rvec = vector![radius, radius, radius];
-        let bbox1 = Aabb::from_points(center1 - rvec, center1 + rvec);
-        let bbox2 = Aabb::from_points(center2 - rvec, center2 + rvec);
+        let bbox = center
+            .hull()
+            .into_iter()
+            .map(|c| Aabb::from_points(c - rvec, c + rvec))
+            .reduce(|a, b| Aabb::merge(&a, &b))
+            .unwrap();
         Sphere {
-            center1,
+            center,
             radius,
             mat: mat.clone(),
-            center_vec: Some(center2 - center1),
-            bbox: Aabb::merge(&bbox1, &bbox2),
+            bbox,
         }
     }
 
+    /// Returns the path the center of this [`Sphere`] follows.
+    pub fn center(&self) -> &Curve<Point3<f64>> {
+        &self.center
+    }
+
+    /// Returns the radius of this [`Sphere`].
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    /// Returns a reference to the material of this [`Sphere`].
+    pub fn material(&self) -> &Material {
+        &self.mat
+    }
+
     pub fn get_uv(&self, point: Vector3<f64>) -> (f64, f64) {
         let theta = f64::acos(-point.y);
         let phi = f64::atan2(-point.z, point.x) + PI;
 
         (phi / (2. * PI), theta / PI)
     }
-
-    fn center(&self, time: f64) -> Point3<f64> {
-        match &self.center_vec {
-            Some(vec) => self.center1 + time * vec,
-            None => self.center1,
-        }
-    }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
-        let oc = ray.origin() - self.center(*ray.time());
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let center = self.center.at(*ray.time());
+        let oc = ray.origin() - center;
         let a = ray.direction().norm_squared();
         let half_b = oc.dot(ray.direction());
         let c = oc.norm_squared() - self.radius * self.radius;
@@ -83,7 +110,7 @@ impl Hittable for Sphere {
         }
 
         let point = ray.at(root);
-        let outward_normal = (point - self.center1) / self.radius;
+        let outward_normal = (point - center) / self.radius;
         let (u, v) = self.get_uv(outward_normal);
 
         Some(HitRecord::new(
@@ -100,4 +127,33 @@ impl Hittable for Sphere {
     fn bounding_box(&self) -> &Aabb {
         &self.bbox
     }
+
+    fn pdf_value(&self, origin: &Point3<f64>, direction: &Vector3<f64>, time: f64) -> f64 {
+        let ray = Ray::with_time(*origin, *direction, time);
+        if self.hit(&ray, Interval::new(0.001, f64::MAX)).is_none() {
+            return 0.;
+        }
+
+        let distance_squared = (self.center.at(time) - origin).norm_squared();
+        if distance_squared <= self.radius * self.radius {
+            return 1. / (4. * PI);
+        }
+        let cos_theta_max = (1. - self.radius * self.radius / distance_squared).sqrt();
+        1. / (2. * PI * (1. - cos_theta_max))
+    }
+
+    fn random(&self, origin: &Point3<f64>, u: [f64; 2], time: f64) -> Vector3<f64> {
+        // From outside, pick uniformly over the cone of directions the sphere fills
+        let direction = self.center.at(time) - origin;
+        let distance_squared = direction.norm_squared();
+        if distance_squared <= self.radius * self.radius {
+            return square_to_sphere(u);
+        }
+        let cos_theta_max = (1. - self.radius * self.radius / distance_squared).sqrt();
+        to_world(&square_to_cone(u, cos_theta_max), &direction.normalize())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
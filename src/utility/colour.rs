@@ -0,0 +1,35 @@
+use nalgebra::Vector3;
+
+/// Returns the luminance of a linear RGB colour, using the Rec. 709 weights.
+pub fn luminance(colour: &Vector3<f64>) -> f64 {
+    0.2126 * colour.x + 0.7152 * colour.y + 0.0722 * colour.z
+}
+
+/// Encodes a linear value in [0, 1] with the sRGB transfer curve, which is what displays expect.
+pub fn linear_to_srgb(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1. / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_curve_matches_known_values() {
+        assert_eq!(linear_to_srgb(0.), 0.);
+        assert!((linear_to_srgb(1.) - 1.).abs() < 1e-12);
+        assert!((linear_to_srgb(0.18) - 0.461356).abs() < 1e-6);
+        assert!((linear_to_srgb(0.5) - 0.735357).abs() < 1e-6);
+    }
+
+    #[test]
+    fn srgb_curve_joins_up_where_the_power_law_starts() {
+        let below = linear_to_srgb(0.0031308);
+        let above = linear_to_srgb(0.0031308 + 1e-12);
+        assert!((below - above).abs() < 1e-6, "{below} then {above}");
+    }
+}
@@ -1,4 +1,6 @@
+mod colour;
 mod interval;
 pub mod random;
 
+pub use colour::{linear_to_srgb, luminance};
 pub use interval::Interval;
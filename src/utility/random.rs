@@ -1,18 +1,77 @@
+use std::cell::RefCell;
+
 use nalgebra::{vector, Vector3};
-use rand::Rng;
+use rand::{
+    distributions::{uniform::SampleRange, uniform::SampleUniform, Distribution, Standard},
+    rngs::SmallRng,
+    Rng, SeedableRng,
+};
+
+thread_local! {
+    /// The generator behind every random number drawn while building and rendering a scene.
+    /// It starts from a fixed seed on every thread, so nothing depends on the system entropy.
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::seed_from_u64(0));
+}
+
+/// Restarts this thread's generator from `seed`.
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
+}
+
+/// Restarts this thread's generator for taking sample number `sample` of pixel number `pixel`.
+/// Every sample gets its own stream, so an image comes out the same whichever thread renders
+/// each pixel and in whatever order.
+pub fn seed_sample(seed: u64, pixel: u32, sample: u32) {
+    self::seed(hash(&[seed, pixel as u64, sample as u64]));
+}
+
+/// Hashes a list of numbers into one well mixed number. Used to give each pixel and dimension
+/// its own random choices without keeping any state.
+pub fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0, |h, &v| mix(h ^ mix(v)))
+}
+
+/// Hashes a string of bytes, such as a description of a scene, into one well mixed number.
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    let words = bytes.chunks(8).map(|chunk| {
+        let mut word = [0; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        u64::from_le_bytes(word)
+    });
+    // The length is mixed in too, so that trailing zeros are not lost
+    words.fold(mix(bytes.len() as u64), |h, v| mix(h ^ mix(v)))
+}
+
+/// Runs `f` with this thread's generator.
+pub fn with_rng<T>(f: impl FnOnce(&mut SmallRng) -> T) -> T {
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
+/// Returns a random value from this thread's generator, like [`rand::random`].
+pub fn random<T>() -> T
+where
+    Standard: Distribution<T>,
+{
+    with_rng(|rng| rng.gen())
+}
+
+/// Returns a random value in `range` from this thread's generator.
+pub fn random_range<T: SampleUniform, R: SampleRange<T>>(range: R) -> T {
+    with_rng(|rng| rng.gen_range(range))
+}
 
 pub fn rng_vec() -> Vector3<f64> {
-    let mut rng = rand::thread_rng();
-    Vector3::new(rng.gen(), rng.gen(), rng.gen())
+    with_rng(|rng| Vector3::new(rng.gen(), rng.gen(), rng.gen()))
 }
 
 pub fn rng_vec_bound(min: f64, max: f64) -> Vector3<f64> {
-    let mut rng = rand::thread_rng();
-    Vector3::new(
-        rng.gen_range(min..=max),
-        rng.gen_range(min..=max),
-        rng.gen_range(min..=max),
-    )
+    with_rng(|rng| {
+        Vector3::new(
+            rng.gen_range(min..=max),
+            rng.gen_range(min..=max),
+            rng.gen_range(min..=max),
+        )
+    })
 }
 
 pub fn rng_unit_sphere() -> Vector3<f64> {
@@ -37,10 +96,20 @@ pub fn rng_on_hemisphere(normal: &Vector3<f64>) -> Vector3<f64> {
 }
 
 pub fn rng_in_unit_disk() -> Vector3<f64> {
-    let mut rng = rand::thread_rng();
-    let mut p = vector![rng.gen_range((-1.)..=1.), rng.gen_range((-1.)..=1.), 0.];
-    while p.norm_squared() >= 1. {
-        p = vector![rng.gen_range((-1.)..=1.), rng.gen_range((-1.)..=1.), 0.];
-    }
-    p
+    with_rng(|rng| {
+        let mut p = vector![rng.gen_range((-1.)..=1.), rng.gen_range((-1.)..=1.), 0.];
+        while p.norm_squared() >= 1. {
+            p = vector![rng.gen_range((-1.)..=1.), rng.gen_range((-1.)..=1.), 0.];
+        }
+        p
+    })
+}
+
+/// The SplitMix64 finaliser, which spreads nearby inputs such as neighbouring pixel numbers
+/// across unrelated outputs.
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
 }
@@ -1,9 +1,8 @@
-use std::sync::Arc;
+use std::{any::Any, sync::Arc};
 
 use nalgebra::vector;
-use rand::random;
 
-use crate::{core::{HitRecord, Hittable, Ray}, materials::{Material, Texture}, shapes::Aabb, utility::Interval};
+use crate::{core::{HitRecord, Hittable, Ray}, materials::{Material, Texture}, shapes::Aabb, sampling, utility::Interval};
 
 pub struct ConstantMedium {
     boundary: Arc<dyn Hittable>,
@@ -21,6 +20,21 @@ impl ConstantMedium {
             },
         }
     }
+
+    /// Returns the shape filled by the medium.
+    pub fn boundary(&self) -> &Arc<dyn Hittable> {
+        &self.boundary
+    }
+
+    /// Returns the density of the medium.
+    pub fn density(&self) -> f64 {
+        -1. / self.neg_inv_density
+    }
+
+    /// Returns the material light scatters off inside the medium.
+    pub fn phase_function(&self) -> &Material {
+        &self.phase_function
+    }
 }
 
 impl Hittable for ConstantMedium {
@@ -28,11 +42,15 @@ impl Hittable for ConstantMedium {
         self.boundary.bounding_box()
     }
 
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
     fn hit(
         &self,
         ray: &Ray,
         ray_t: Interval,
-    ) -> Option<HitRecord> {
+    ) -> Option<HitRecord<'_>> {
         let mut rec1 = self.boundary.hit(ray, Interval::universe())?;
         let mut rec2 = self
             .boundary
@@ -56,7 +74,7 @@ impl Hittable for ConstantMedium {
 
         let ray_length = ray.direction().norm();
         let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-        let hit_distance = self.neg_inv_density * random::<f64>().ln();
+        let hit_distance = self.neg_inv_density * (1. - sampling::get_1d()).ln();
 
         if hit_distance > distance_inside_boundary {
             return None;
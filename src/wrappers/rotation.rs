@@ -1,8 +1,9 @@
-use std::sync::Arc;
+use std::{any::Any, sync::Arc};
 
-use nalgebra::{point, vector};
+use nalgebra::{point, vector, Point3, Vector3};
 
 use crate::{
+    animation::Curve,
     core::{HitRecord, Hittable, Ray},
     shapes::Aabb,
     utility::Interval,
@@ -10,6 +11,8 @@ use crate::{
 
 pub struct RotateY {
     object: Arc<dyn Hittable>,
+    angle: Curve<f64>,
+    /// The sine and cosine of the angle, if it does not change.
     sin_theta: f64,
     cos_theta: f64,
     bbox: Aabb,
@@ -17,11 +20,32 @@ pub struct RotateY {
 
 impl RotateY {
     pub fn new(object: Arc<dyn Hittable>, angle: f64) -> RotateY {
-        let radians = angle.to_radians();
+        RotateY::animated(object, Curve::constant(angle))
+    }
+
+    /// Creates a rotation whose angle follows `angle` over time.
+    pub fn animated(object: Arc<dyn Hittable>, angle: Curve<f64>) -> RotateY {
+        let radians = angle.at(0.).to_radians();
         let sin_theta = radians.sin();
         let cos_theta = radians.cos();
         let mut bbox = object.bounding_box().clone();
 
+        if !angle.is_constant() {
+            // Rather than follow the corners around, bound the whole circle they could sweep
+            let radius = [bbox.x.min, bbox.x.max]
+                .into_iter()
+                .flat_map(|x| [bbox.z.min, bbox.z.max].map(|z| x.hypot(z)))
+                .fold(0., f64::max);
+            let across = Interval::new(-radius, radius);
+            return RotateY {
+                bbox: Aabb::new(across, bbox.y, across),
+                object,
+                angle,
+                sin_theta,
+                cos_theta,
+            };
+        }
+
         let mut min = point![f64::MAX, f64::MAX, f64::MAX];
         let mut max = point![f64::MIN, f64::MIN, f64::MIN];
 
@@ -49,48 +73,99 @@ impl RotateY {
 
         RotateY {
             object,
+            angle,
             sin_theta,
             cos_theta,
             bbox,
         }
     }
+
+    /// Returns the object being rotated.
+    pub fn object(&self) -> &Arc<dyn Hittable> {
+        &self.object
+    }
+
+    /// Returns the angle of rotation in degrees over time.
+    pub fn angle(&self) -> &Curve<f64> {
+        &self.angle
+    }
+
+    /// Returns the sine and cosine of the angle at `time`.
+    fn sin_cos(&self, time: f64) -> (f64, f64) {
+        if self.angle.is_constant() {
+            (self.sin_theta, self.cos_theta)
+        } else {
+            self.angle.at(time).to_radians().sin_cos()
+        }
+    }
+}
+
+/// Turns `v` from world space into the space of the rotated object.
+fn to_object(v: &Vector3<f64>, (sin_theta, cos_theta): (f64, f64)) -> Vector3<f64> {
+    vector![
+        cos_theta * v.x - sin_theta * v.z,
+        v.y,
+        sin_theta * v.x + cos_theta * v.z
+    ]
+}
+
+/// Turns `v` from the space of the rotated object back into world space.
+fn from_object(v: &Vector3<f64>, (sin_theta, cos_theta): (f64, f64)) -> Vector3<f64> {
+    vector![
+        cos_theta * v.x + sin_theta * v.z,
+        v.y,
+        -sin_theta * v.x + cos_theta * v.z
+    ]
 }
 
 impl Hittable for RotateY {
-    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let (sin_theta, cos_theta) = self.sin_cos(*ray.time());
+
         let mut origin = *ray.origin();
         let mut direction = *ray.direction();
 
-        origin[0] = self.cos_theta * ray.origin()[0] - self.sin_theta * ray.origin()[2];
-        origin[2] = self.sin_theta * ray.origin()[0] + self.cos_theta * ray.origin()[2];
+        origin[0] = cos_theta * ray.origin()[0] - sin_theta * ray.origin()[2];
+        origin[2] = sin_theta * ray.origin()[0] + cos_theta * ray.origin()[2];
 
-        direction[0] = self.cos_theta * ray.direction()[0] - self.sin_theta * ray.direction()[2];
-        direction[2] = self.sin_theta * ray.direction()[0] + self.cos_theta * ray.direction()[2];
+        direction[0] = cos_theta * ray.direction()[0] - sin_theta * ray.direction()[2];
+        direction[2] = sin_theta * ray.direction()[0] + cos_theta * ray.direction()[2];
 
         let rotated_ray = Ray::with_time(origin, direction, *ray.time());
 
-        let rec = self.object.hit(&rotated_ray, ray_t);
+        let mut rec = self.object.hit(&rotated_ray, ray_t)?;
+        let mut p = rec.point;
 
-        if rec.is_none() {
-            None
-        } else {
-            let mut rec = rec.unwrap();
-            let mut p = rec.point;
-
-            p.x = self.cos_theta * rec.point[0] + self.sin_theta * rec.point[2];
-            p.z = -self.sin_theta * rec.point[0] + self.cos_theta * rec.point[2];
+        p.x = cos_theta * rec.point[0] + sin_theta * rec.point[2];
+        p.z = -sin_theta * rec.point[0] + cos_theta * rec.point[2];
 
-            let mut normal = rec.normal;
-            normal.x = self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
-            normal.z = -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2];
+        let mut normal = rec.normal;
+        normal.x = cos_theta * rec.normal[0] + sin_theta * rec.normal[2];
+        normal.z = -sin_theta * rec.normal[0] + cos_theta * rec.normal[2];
 
-            rec.point = p;
-            rec.normal = normal;
+        rec.point = p;
+        rec.normal = normal;
 
-            Some(rec)
-        }
+        Some(rec)
     }
     fn bounding_box(&self) -> &Aabb {
         &self.bbox
     }
+
+    fn pdf_value(&self, origin: &Point3<f64>, direction: &Vector3<f64>, time: f64) -> f64 {
+        let rotation = self.sin_cos(time);
+        let origin = Point3::from(to_object(&origin.coords, rotation));
+        self.object
+            .pdf_value(&origin, &to_object(direction, rotation), time)
+    }
+
+    fn random(&self, origin: &Point3<f64>, u: [f64; 2], time: f64) -> Vector3<f64> {
+        let rotation = self.sin_cos(time);
+        let origin = Point3::from(to_object(&origin.coords, rotation));
+        from_object(&self.object.random(&origin, u, time), rotation)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
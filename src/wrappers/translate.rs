@@ -1,8 +1,9 @@
-use std::sync::Arc;
+use std::{any::Any, sync::Arc};
 
-use nalgebra::Vector3;
+use nalgebra::{Point3, Vector3};
 
 use crate::{
+    animation::Curve,
     core::{HitRecord, Hittable, Ray},
     shapes::Aabb,
     utility::Interval,
@@ -10,29 +11,50 @@ use crate::{
 
 pub struct Translate {
     object: Arc<dyn Hittable>,
-    offset: Vector3<f64>,
+    offset: Curve<Vector3<f64>>,
     bbox: Aabb,
 }
 
 impl Translate {
     pub fn new(object: Arc<dyn Hittable>, offset: Vector3<f64>) -> Translate {
-        let bbox = object.bounding_box() + offset;
+        Translate::animated(object, Curve::constant(offset))
+    }
+
+    /// Creates a translation whose offset follows `offset` over time.
+    pub fn animated(object: Arc<dyn Hittable>, offset: Curve<Vector3<f64>>) -> Translate {
+        let bbox = offset
+            .hull()
+            .into_iter()
+            .map(|offset| object.bounding_box() + offset)
+            .reduce(|a, b| Aabb::merge(&a, &b))
+            .unwrap();
         Translate {
             object,
             offset,
             bbox,
         }
     }
+
+    /// Returns the object being moved.
+    pub fn object(&self) -> &Arc<dyn Hittable> {
+        &self.object
+    }
+
+    /// Returns how far the object is moved over time.
+    pub fn offset(&self) -> &Curve<Vector3<f64>> {
+        &self.offset
+    }
 }
 
 impl Hittable for Translate {
-    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
-        let offset_ray = Ray::with_time(ray.origin() - self.offset, *ray.direction(), *ray.time());
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let offset = self.offset.at(*ray.time());
+        let offset_ray = Ray::with_time(ray.origin() - offset, *ray.direction(), *ray.time());
 
         let rec = self.object.hit(&offset_ray, ray_t);
         match rec {
             Some(mut r) => {
-                r.point += self.offset;
+                r.point += offset;
                 Some(r)
             }
             None => None,
@@ -42,4 +64,18 @@ impl Hittable for Translate {
     fn bounding_box(&self) -> &Aabb {
         &self.bbox
     }
+
+    fn pdf_value(&self, origin: &Point3<f64>, direction: &Vector3<f64>, time: f64) -> f64 {
+        let offset = self.offset.at(time);
+        self.object.pdf_value(&(origin - offset), direction, time)
+    }
+
+    fn random(&self, origin: &Point3<f64>, u: [f64; 2], time: f64) -> Vector3<f64> {
+        let offset = self.offset.at(time);
+        self.object.random(&(origin - offset), u, time)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
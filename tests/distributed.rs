@@ -0,0 +1,96 @@
+#![cfg(unix)]
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Lines},
+    path::PathBuf,
+    process::{Child, ChildStdout, Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+/// The scene and camera settings every process renders with.
+const SCENE: [&str; 8] = ["-s", "cornel_box", "-w", "24", "-n", "32", "--seed", "1"];
+
+fn raytracer(mode: &str) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_raytracer"));
+    command.args(["-m", mode]).args(SCENE);
+    command
+}
+
+fn worker(address: &str) -> Child {
+    raytracer("worker")
+        .args(["--address", address])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap()
+}
+
+/// Reads lines the coordinator printed until one starts with `prefix`, returning the rest of it.
+fn wait_for(lines: &mut Lines<BufReader<ChildStdout>>, prefix: &str) -> String {
+    for line in lines.by_ref() {
+        if let Some(rest) = line.unwrap().strip_prefix(prefix) {
+            return rest.to_string();
+        }
+    }
+    panic!("the coordinator stopped before printing `{prefix}`");
+}
+
+fn output(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("raytracer-{}-{name}.pfm", std::process::id()))
+}
+
+#[test]
+fn lost_workers_tiles_are_rendered_by_others() {
+    let distributed = output("distributed");
+    let mut coordinator = raytracer("coordinator")
+        .args(["--address", "127.0.0.1:0", "--tile-size", "8"])
+        .args(["--worker-timeout", "3"])
+        .arg("-o")
+        .arg(&distributed)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(coordinator.stdout.take().unwrap()).lines();
+    let address = wait_for(&mut lines, "Waiting for workers on ");
+
+    // One worker hangs partway through a tile without closing its connection, so the
+    // coordinator has to notice the silence
+    let mut hung = worker(&address);
+    wait_for(&mut lines, "Worker ");
+    thread::sleep(Duration::from_millis(200));
+    let stopped = Command::new("kill")
+        .args(["-STOP", &hung.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(stopped.success());
+
+    // Another is killed partway through a tile, which closes its connection
+    let mut killed = worker(&address);
+    wait_for(&mut lines, "Worker ");
+    thread::sleep(Duration::from_millis(200));
+    killed.kill().unwrap();
+    killed.wait().unwrap();
+
+    let mut finisher = worker(&address);
+    assert!(coordinator.wait().unwrap().success());
+    assert!(finisher.wait().unwrap().success());
+    hung.kill().unwrap();
+    hung.wait().unwrap();
+
+    let single = output("single");
+    let status = raytracer("headless")
+        .arg("-o")
+        .arg(&single)
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let (expected, actual) = (fs::read(&single).unwrap(), fs::read(&distributed).unwrap());
+    fs::remove_file(single).unwrap();
+    fs::remove_file(distributed).unwrap();
+    assert!(expected == actual, "the distributed render differs");
+}